@@ -1,4 +1,11 @@
 fn main() {
+    // Without the ui feature (see Cargo.toml), there's no Slint code to compile
+    #[cfg(feature = "ui")]
+    compile_ui();
+}
+
+#[cfg(feature = "ui")]
+fn compile_ui() {
     let config = slint_build::CompilerConfiguration::new().with_style("fluent-light".into());
 
     // material-light: too big
@@ -1,14 +1,19 @@
 #!/usr/bin/env python3
 """Scan an external HDD and emit a JSONL listing of video files.
 
-The output is consumed by videofinder's merger: one JSON object per line,
-each one ready to be inserted as a Tape row (CODE_TAPE is assigned at
-merge time). Paths are stored relative to basedir to match the existing
-kvideomanager schema.
+The output is consumed by videofinder's merger: a first line describing
+the scan ({"meta": {"scan_date": ..., "scanner": ...}}), then one JSON
+object per line, each one ready to be inserted as a Tape row (CODE_TAPE
+is assigned at merge time). Paths are stored relative to basedir to match
+the existing kvideomanager schema.
 
 Replaces helper_import_filenames_into_db.pl. Same filtering and
 title-shaping rules; no SQLite writes here.
 
+Superseded by `videofinder scan <mountpoint>`, which writes the same rows
+with its default rules (src/scan_rules.json); keep the two in sync if this
+script is still used.
+
 Usage:
     cd /run/media/dfaure/<HDD-root>
     scan_hdd.py
@@ -21,6 +26,7 @@ CWD must contain:
 """
 
 import argparse
+import datetime
 import json
 import os
 import re
@@ -46,6 +52,9 @@ DATE_PATTERN_NEW = re.compile(r"(\d{2})-(\d{2})-(\d{4})")
 DATE_PATTERN_OLD = re.compile(r"-(\d{2})(\d{2})(\d{4})")
 UNNAMED_PATTERN = re.compile(r"unn?amed", re.IGNORECASE)
 
+# Recorded in the slice metadata, bump when the output changes.
+SCANNER = "scan_hdd.py 1"
+
 ID_FILE = "id"
 CANDIDATE_BASEDIRS = ["films", "films2", "films3"]
 
@@ -175,6 +184,11 @@ def main():
     written = 0
     skipped = 0
     with open(output, "w", encoding="utf-8") as out:
+        meta = {
+            "scan_date": datetime.date.today().isoformat(),
+            "scanner": SCANNER,
+        }
+        out.write(json.dumps({"meta": meta}, ensure_ascii=False) + "\n")
         for path in sorted(all_paths):
             rec = derive_record(path, all_paths, location)
             if rec is None:
@@ -0,0 +1,575 @@
+//! The Slint app: the search window, with its browse views, and the details window.
+
+use chrono::{DateTime, Local};
+use std::cell::RefCell;
+use std::error::Error;
+use std::fs::File;
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::catalog::{Catalog, Folder, SearchHit};
+use crate::download;
+use crate::download::ImageForDirHash;
+use crate::download::download_db;
+use crate::download::parse_file_list;
+use crate::enums::SupportType;
+use crate::image_handling::download_image;
+use crate::image_handling::image_url;
+use crate::merge::MERGED_SCHEMA_VERSION;
+use crate::query::{SearchFilter, SearchQuery, SortOrder};
+use crate::result_model::{PAGE_SIZE, ResultModel};
+use crate::search_thread::{SearchThread, SearchTicket};
+use crate::slint_adapter::{
+    folder_entries, folder_summary, loan_data, loan_item, parse_due_date, personal_data,
+    record_wrapper, search_filter, season_data, shelf_row_data, suggestion_data, user_data,
+};
+use crate::sqlsearch::SearchResults;
+use crate::user_data::UserData;
+use slint::{ModelRc, SharedString, VecModel};
+
+// Include the slint-generated code
+slint::include_modules!();
+
+/// How many suggestions are shown under the search field
+const MAX_SUGGESTIONS: usize = 8;
+
+thread_local! {
+    /// The catalog used on the UI thread, the search thread has its own
+    static CATALOG: Rc<RefCell<Catalog>> = Rc::new(RefCell::new(Catalog::default()));
+    /// Runs the searches and fetches their pages of results, see result_model.rs
+    static SEARCH_THREAD: Rc<SearchThread> = Rc::new(SearchThread::start());
+}
+
+#[cfg(target_os = "android")]
+#[unsafe(no_mangle)]
+fn android_main(app: slint::android::AndroidApp) -> Result<(), Box<dyn Error>> {
+    // Log to file, on Android
+    flexi_logger::Logger::try_with_env_or_str("debug,android_activity::activity_impl::glue=off")?
+        .log_to_file(flexi_logger::FileSpec::try_from(
+            "/storage/emulated/0/Download/videofinder_log.txt",
+        )?)
+        .format(flexi_logger::detailed_format)
+        .start()?;
+
+    log::info!("videofinder started");
+    slint::android::init(app).unwrap();
+    log::debug!("slint::android initialized");
+    let ret = videofinder_main();
+    if let Err(ref e) = ret {
+        log::error!("{:?}", e);
+    }
+    // When we get here, exit process so Android restarts fresh next time
+    std::process::exit(0);
+}
+
+fn show_db_status(
+    ui: &AppWindow,
+    catalog: &Rc<RefCell<Catalog>>,
+    image_for_dir_hash: &Rc<RefCell<ImageForDirHash>>,
+) {
+    let db_full_path = download::db_full_path();
+    if !db_full_path.exists() {
+        // Not an error, if it's a first time user. Just let them download it.
+        let status = "No DB, click here to download:";
+        ui.set_status(status.into());
+    } else {
+        // Check if readable, to debug permission problems on Android
+        match File::open(db_full_path) {
+            Ok(file) => {
+                match file.metadata() {
+                    Ok(metadata) => {
+                        let mut catalog = catalog.borrow_mut();
+                        if catalog.schema_version().unwrap_or(0) < MERGED_SCHEMA_VERSION {
+                            // Produced by an older version, search would fail on it.
+                            ui.set_status("DB format is outdated, click here to download:".into());
+                            return;
+                        }
+                        match catalog.locations() {
+                            Ok(locations) => {
+                                let mut items: Vec<slint::SharedString> =
+                                    vec!["All locations".into()];
+                                items.extend(locations.into_iter().map(Into::into));
+                                ui.set_locations(Rc::new(VecModel::from(items)).into());
+                            }
+                            Err(e) => log::warn!("Failed to list locations: {}", e),
+                        }
+                        show_browse_lists(ui, &mut catalog);
+                        if let Ok(modified) = metadata.modified() {
+                            let datetime: DateTime<Local> = modified.into();
+                            let time_str = format!(
+                                "DB last updated: {}",
+                                datetime.format("%d/%m/%Y %H:%M:%S")
+                            );
+                            ui.set_status(time_str.into());
+
+                            if let Ok(hash) = parse_file_list() {
+                                *image_for_dir_hash.borrow_mut() = hash;
+                            }
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to get metadata: {}", e);
+                    }
+                }
+                ui.set_status("DB last updated: unknown".into());
+            }
+            Err(e) => {
+                log::error!("File::open failed: {}", e);
+                let error_msg = format!("DB exists but cannot be opened: {}", e);
+                ui.set_status(error_msg.into());
+            }
+        }
+    }
+}
+
+/// The top level of the browse views
+fn show_browse_lists(ui: &AppWindow, catalog: &mut Catalog) {
+    let model = |names: rusqlite::Result<Vec<String>>, what: &str| -> ModelRc<SharedString> {
+        let names = names.unwrap_or_else(|e| {
+            log::warn!("Failed to list {}: {}", what, e);
+            Vec::new()
+        });
+        let items: Vec<SharedString> = names.into_iter().map(Into::into).collect();
+        Rc::new(VecModel::from(items)).into()
+    };
+    ui.set_series(model(catalog.series(), "series"));
+    ui.set_shelf_locations(model(catalog.shelf_locations(), "shelf locations"));
+    ui.set_hdd_locations(model(catalog.hdd_locations(), "HDD locations"));
+}
+
+/// The contents of the folder at `path` in the disk view
+fn show_folder(ui: &AppWindow, tree: &Folder, path: &str) {
+    let Some(folder) = tree.find(path) else {
+        log::warn!("No folder {:?}", path);
+        return;
+    };
+    ui.set_folder_path(folder.path.as_str().into());
+    ui.set_folder_parent(folder.path.rsplit_once('/').map_or("", |(parent, _)| parent).into());
+    ui.set_folder_summary(folder_summary(folder).into());
+    ui.set_folder_entries(Rc::new(VecModel::from(folder_entries(folder))).into());
+}
+
+/// The loans of the support with the key `support_key`, in the details window
+fn show_support_loans(ui: &AppWindow, catalog: &mut Catalog, support_key: &str) {
+    let loans = catalog.loans(support_key).unwrap_or_else(|e| {
+        log::warn!("Failed to read the loans: {}", e);
+        Vec::new()
+    });
+    let (current, history) = match loans.first() {
+        Some(loan) if loan.returned_on.is_none() => (loan_data(loan), &loans[1..]),
+        _ => (LoanData::default(), &loans[..]),
+    };
+    let history: Vec<LoanData> = history.iter().map(loan_data).collect();
+    ui.set_details_loan(current);
+    ui.set_details_loan_history(Rc::new(VecModel::from(history)).into());
+}
+
+/// The supports currently lent, in the loans view
+fn show_current_loans(ui: &AppWindow, catalog: &mut Catalog) {
+    let loans = catalog.current_loans().unwrap_or_else(|e| {
+        log::warn!("Failed to list the loans: {}", e);
+        Vec::new()
+    });
+    let items: Vec<LoanItemData> = loans.into_iter().map(loan_item).collect();
+    ui.set_loans(Rc::new(VecModel::from(items)).into());
+}
+
+fn show_search_results(
+    ui: &AppWindow,
+    result: rusqlite::Result<(SearchResults, Vec<SearchHit>)>,
+    ticket: SearchTicket,
+) {
+    ui.set_search_error("".into());
+    ui.set_search_hint("".into());
+    match result {
+        Ok((results, first_page)) => {
+            log::info!("displaying {} results", results.count());
+            if results.count() == 0 {
+                ui.set_search_error("No results found".into());
+            } else if results.approximate {
+                ui.set_search_hint("showing approximate matches".into());
+            }
+            ui.set_result_count(results.count() as i32);
+            let start_time_set = Instant::now();
+            let search_thread = SEARCH_THREAD.with(Rc::clone);
+            let model = ResultModel::new(results, first_page, ticket, search_thread, ui.as_weak());
+            ui.set_result_items(Rc::new(model).into());
+            log::info!("set_result_items: {:?}", start_time_set.elapsed());
+        }
+        Err(e) => {
+            let error_msg = format!("Error: {}", e);
+            log::warn!("{}", error_msg);
+            ui.set_search_error(error_msg.into());
+        }
+    }
+}
+
+/// The film (if any) and the key of the support shown in the details window,
+/// to save the changes to their user data.
+type DetailsKey = Option<(Option<i32>, String)>;
+
+fn open_details_window(
+    ui: &AppWindow,
+    catalog: &mut Catalog,
+    film_code: i32,
+    support_code: i32,
+    image_for_dir_hash: &ImageForDirHash,
+    current_image_download_url: &Rc<RefCell<Option<String>>>,
+    details_key: &RefCell<DetailsKey>,
+) -> String {
+    ui.set_details_error("".into());
+    ui.set_loan_error("".into());
+    ui.set_details_image(slint::Image::default());
+    *current_image_download_url.borrow_mut() = None;
+    *details_key.borrow_mut() = None;
+    log::info!("item clicked film {} support {}", film_code, support_code);
+    let details = catalog.support(support_code).and_then(|support| {
+        // HDD files are shown without their film
+        let film = if film_code != 0 && support.support_type != SupportType::ComputerFile {
+            Some(catalog.film(film_code)?)
+        } else {
+            None
+        };
+        Ok((support, film))
+    });
+    match details {
+        Ok((support, film)) => {
+            let image_path = film.as_ref().and_then(|film| film.image_path.clone());
+            let film_code = film.as_ref().map(|film| film.code);
+            let data = catalog.user_data(film_code, &support.key).unwrap_or_else(|e| {
+                log::warn!("Failed to read the user data: {}", e);
+                UserData::default()
+            });
+            ui.set_details_personal(personal_data(data));
+            show_support_loans(ui, catalog, &support.key);
+            *details_key.borrow_mut() = Some((film_code, support.key.clone()));
+            ui.set_details_record(record_wrapper(support, film));
+            image_url(image_path, image_for_dir_hash)
+        }
+        Err(e) => {
+            let error_msg = format!("Error: {}", e);
+            log::warn!("{}", error_msg);
+            ui.set_details_error(error_msg.into());
+            String::new()
+        }
+    }
+}
+
+pub fn videofinder_main() -> Result<(), Box<dyn Error>> {
+    std::panic::set_hook(Box::new(|info| {
+        log::error!("Panic occurred: {}", info);
+    }));
+
+    let ui = AppWindow::new()?;
+    let image_for_dir_hash: Rc<RefCell<ImageForDirHash>> =
+        Rc::new(RefCell::new(ImageForDirHash::new()));
+    let current_image_download_url: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
+    let details_key: Rc<RefCell<DetailsKey>> = Rc::new(RefCell::new(None));
+    let group_by_support: Rc<RefCell<bool>> = Rc::new(RefCell::new(true));
+    let filter: Rc<RefCell<SearchFilter>> = Rc::new(RefCell::new(SearchFilter::default()));
+    let sort_order: Rc<RefCell<SortOrder>> = Rc::new(RefCell::new(SortOrder::default()));
+    let catalog = CATALOG.with(Rc::clone);
+
+    // Show initial status and fill in image_for_dir_hash if the file is already present
+    show_db_status(&ui, &catalog, &image_for_dir_hash);
+
+    ui.on_set_group_by_support({
+        let group_by_support = group_by_support.clone();
+        move |b| {
+            log::info!("set_group_by_support: {}", b);
+            *group_by_support.borrow_mut() = b;
+        }
+    });
+
+    ui.set_search_filter(SearchFilterData {
+        tape: true,
+        dvd: true,
+        bluray: true,
+        computer_file: true,
+        ..Default::default()
+    });
+    ui.on_set_sort_order({
+        let sort_order = sort_order.clone();
+        move |index| {
+            // Same order as the sort ComboBox in app-window.slint
+            *sort_order.borrow_mut() = match index {
+                1 => SortOrder::Year,
+                2 => SortOrder::Duration,
+                3 => SortOrder::Location,
+                4 => SortOrder::RecentlyAdded,
+                _ => SortOrder::Title,
+            };
+        }
+    });
+    ui.on_search_filter_changed({
+        let filter = filter.clone();
+        move |data| {
+            *filter.borrow_mut() = search_filter(&data);
+        }
+    });
+
+    ui.on_search({
+        let ui_handle = ui.as_weak();
+        let search_thread = SEARCH_THREAD.with(Rc::clone);
+        move |text| {
+            log::info!("searching for {:?}", text);
+            let ui = ui_handle.unwrap();
+            let query = match SearchQuery::parse(&text) {
+                Ok(query) => query,
+                Err(e) => {
+                    search_thread.cancel();
+                    // The pages of the previous results won't be fetched anymore
+                    ui.set_result_count(0);
+                    ui.set_result_items(ModelRc::default());
+                    ui.set_search_hint("".into());
+                    ui.set_search_error(e.to_string().into());
+                    return;
+                }
+            };
+            let filter = filter.borrow().clone();
+            let sort_order = *sort_order.borrow();
+            let group_by_support = *group_by_support.borrow();
+            let ui_handle = ui_handle.clone();
+            search_thread.search(move |catalog, ticket| {
+                let start_time_sql = Instant::now();
+                let result = catalog
+                    .search(&query, &filter, sort_order, group_by_support)
+                    .and_then(|results| {
+                        // None if a sync just replaced the file, the model will search again
+                        let first_page = catalog.hits(&results, 0, PAGE_SIZE)?.unwrap_or_default();
+                        Ok((results, first_page))
+                    });
+                log::info!("SQL search: {:?}", start_time_sql.elapsed());
+                let _ = slint::invoke_from_event_loop(move || {
+                    // Typing went on in the meantime (the search might have been interrupted)
+                    if !ticket.is_latest() {
+                        log::debug!("dropping the results of an outdated search");
+                        return;
+                    }
+                    show_search_results(&ui_handle.unwrap(), result, ticket);
+                });
+            });
+        }
+    });
+
+    // On a thread of their own too, so that they neither slow down typing nor
+    // interrupt the search
+    let suggest_thread = SearchThread::start();
+    ui.on_suggest({
+        let ui_handle = ui.as_weak();
+        move |text| {
+            let ui_handle = ui_handle.clone();
+            suggest_thread.search(move |catalog, ticket| {
+                let suggestions = catalog.suggestions(&text, MAX_SUGGESTIONS);
+                let _ = slint::invoke_from_event_loop(move || {
+                    // The text changed in the meantime
+                    if !ticket.is_latest() {
+                        return;
+                    }
+                    let suggestions = suggestions.unwrap_or_else(|e| {
+                        log::warn!("Failed to list the suggestions: {}", e);
+                        Vec::new()
+                    });
+                    let items: Vec<SuggestionData> =
+                        suggestions.into_iter().map(suggestion_data).collect();
+                    ui_handle.unwrap().set_suggestions(Rc::new(VecModel::from(items)).into());
+                });
+            });
+        }
+    });
+
+    ui.on_item_clicked({
+        let ui_handle = ui.as_weak();
+        let catalog = catalog.clone();
+        let image_for_dir_hash = image_for_dir_hash.clone();
+        let current_image_download_url = current_image_download_url.clone();
+        let details_key = details_key.clone();
+        move |film_code, support_code| {
+            let ui = ui_handle.unwrap();
+            let image_url = open_details_window(
+                &ui,
+                &mut catalog.borrow_mut(),
+                film_code,
+                support_code,
+                &image_for_dir_hash.borrow(),
+                &current_image_download_url,
+                &details_key,
+            );
+            if !image_url.is_empty() {
+                download_image(&ui_handle, &current_image_download_url, image_url);
+            }
+        }
+    });
+
+    ui.on_serie_selected({
+        let ui_handle = ui.as_weak();
+        let catalog = catalog.clone();
+        move |serie_name| {
+            let ui = ui_handle.unwrap();
+            let seasons = match catalog.borrow_mut().seasons(&serie_name) {
+                Ok(seasons) => seasons.into_iter().map(season_data).collect(),
+                Err(e) => {
+                    log::warn!("Failed to list the episodes of {}: {}", serie_name, e);
+                    Vec::new()
+                }
+            };
+            ui.set_seasons(Rc::new(VecModel::from(seasons)).into());
+        }
+    });
+
+    ui.on_shelf_location_selected({
+        let ui_handle = ui.as_weak();
+        let catalog = catalog.clone();
+        move |location| {
+            let ui = ui_handle.unwrap();
+            let rows = match catalog.borrow_mut().shelves(&location) {
+                Ok(rows) => rows.into_iter().map(shelf_row_data).collect(),
+                Err(e) => {
+                    log::warn!("Failed to list the shelves of {}: {}", location, e);
+                    Vec::new()
+                }
+            };
+            ui.set_shelf_rows(Rc::new(VecModel::from(rows)).into());
+        }
+    });
+
+    let disk_tree: Rc<RefCell<Folder>> = Rc::new(RefCell::new(Folder::default()));
+    ui.on_disk_selected({
+        let ui_handle = ui.as_weak();
+        let catalog = catalog.clone();
+        let disk_tree = disk_tree.clone();
+        move |location| {
+            let ui = ui_handle.unwrap();
+            let tree = catalog.borrow_mut().folder_tree(&location).unwrap_or_else(|e| {
+                log::warn!("Failed to list the folders of {}: {}", location, e);
+                Folder::default()
+            });
+            show_folder(&ui, &tree, "");
+            *disk_tree.borrow_mut() = tree;
+        }
+    });
+    ui.on_folder_selected({
+        let ui_handle = ui.as_weak();
+        move |path| {
+            show_folder(&ui_handle.unwrap(), &disk_tree.borrow(), &path);
+        }
+    });
+
+    ui.on_personal_data_edited({
+        let catalog = catalog.clone();
+        let details_key = details_key.clone();
+        move |data| {
+            let Some((film_code, support_key)) = &*details_key.borrow() else {
+                return;
+            };
+            if let Err(e) =
+                catalog.borrow_mut().set_user_data(*film_code, support_key, &user_data(&data))
+            {
+                log::warn!("Failed to save the user data: {}", e);
+            }
+        }
+    });
+
+    ui.on_lend({
+        let ui_handle = ui.as_weak();
+        let catalog = catalog.clone();
+        let details_key = details_key.clone();
+        move |borrower, due_on| {
+            let ui = ui_handle.unwrap();
+            let Some((_, support_key)) = &*details_key.borrow() else {
+                return;
+            };
+            let due_on = match parse_due_date(&due_on) {
+                Ok(due_on) => due_on,
+                Err(e) => {
+                    ui.set_loan_error(e.into());
+                    return;
+                }
+            };
+            ui.set_loan_error("".into());
+            let mut catalog = catalog.borrow_mut();
+            if let Err(e) = catalog.lend(support_key, borrower.trim(), due_on) {
+                log::warn!("Failed to record the loan: {}", e);
+                ui.set_loan_error(format!("Error: {}", e).into());
+            }
+            show_support_loans(&ui, &mut catalog, support_key);
+            show_current_loans(&ui, &mut catalog);
+        }
+    });
+
+    ui.on_support_returned({
+        let ui_handle = ui.as_weak();
+        let catalog = catalog.clone();
+        move || {
+            let ui = ui_handle.unwrap();
+            let Some((_, support_key)) = &*details_key.borrow() else {
+                return;
+            };
+            let mut catalog = catalog.borrow_mut();
+            if let Err(e) = catalog.return_support(support_key) {
+                log::warn!("Failed to record the return: {}", e);
+                ui.set_loan_error(format!("Error: {}", e).into());
+            }
+            show_support_loans(&ui, &mut catalog, support_key);
+            show_current_loans(&ui, &mut catalog);
+        }
+    });
+
+    ui.on_show_loans({
+        let ui_handle = ui.as_weak();
+        let catalog = catalog.clone();
+        move || {
+            show_current_loans(&ui_handle.unwrap(), &mut catalog.borrow_mut());
+        }
+    });
+
+    ui.on_notify_details_window_closed({
+        let current_image_download_url = current_image_download_url.clone();
+        move || {
+            log::debug!("cancel_image_downloads");
+            *current_image_download_url.borrow_mut() = None;
+        }
+    });
+
+    ui.on_download_db({
+        let ui_handle = ui.as_weak();
+        let catalog = catalog.clone();
+        let image_for_dir_hash = image_for_dir_hash.clone();
+
+        move || {
+            let ui = ui_handle.unwrap();
+            ui.set_status("Downloading...".into());
+            let ui_handle = ui_handle.clone();
+            let ui_handle_for_progress = ui_handle.clone();
+            let catalog = catalog.clone();
+            let image_for_dir_hash = image_for_dir_hash.clone();
+            let progress_func = Box::new(move |progress: f32| {
+                ui_handle_for_progress.unwrap().set_progress(progress);
+            });
+            log::info!("on_download_db");
+            if let Err(e) = slint::spawn_local(async_compat::Compat::new(async move {
+                let ui = ui_handle.unwrap();
+                ui.set_download_enabled(false); // prevent re-entrancy
+                let result = download_db(progress_func).await;
+                if let Err(e) = result {
+                    log::warn!("Download error: {e}");
+                    ui.set_status(format!("Download error: {}", e).into());
+                } else {
+                    log::debug!("Download complete");
+                    ui.set_status("Download complete".into());
+                    show_db_status(&ui, &catalog, &image_for_dir_hash);
+                    // The results shown come from the previous file
+                    ui.invoke_refresh_search();
+                }
+                ui.set_download_enabled(true);
+            })) {
+                log::error!("Failed to schedule download: {e}");
+            }
+        }
+    });
+
+    log::debug!("calling run");
+    ui.run()?;
+    Ok(())
+}
@@ -1,7 +1,16 @@
 use std::error::Error;
+use std::path::PathBuf;
 
+use videofinder::Catalog;
+use videofinder::ScanOptions;
+use videofinder::scan_hdd;
+#[cfg(feature = "ui")]
 use videofinder::videofinder_main;
 
+const SCAN_USAGE: &str = "usage: videofinder scan [--verbose] [--dry-run] [--no-probe] \
+                          [--rules <file>] [--output <file>] [--previous <file>] \
+                          [--diff-json <file>] <mountpoint>";
+
 fn main() -> Result<(), Box<dyn Error>> {
     // Prevent tracing (used by winit) from forwarding debug spam to the log crate.
     // Installing any subscriber disables the tracing->log bridge.
@@ -11,5 +20,61 @@ fn main() -> Result<(), Box<dyn Error>> {
     // (I use flexi_logger because on Android I need to log to a file)
     let _logger = flexi_logger::Logger::with(flexi_logger::LevelFilter::Debug).start().unwrap();
 
-    videofinder_main()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("scan") {
+        return scan_command(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("duplicates") {
+        return duplicates_command();
+    }
+
+    #[cfg(feature = "ui")]
+    {
+        videofinder_main()
+    }
+    #[cfg(not(feature = "ui"))]
+    {
+        Err("built without the ui feature, only the scan and duplicates commands are available"
+            .into())
+    }
+}
+
+/// `videofinder scan [options] <mountpoint>`: write the JSONL slice for the
+/// HDD mounted at <mountpoint>, see scan.rs, and report the changes since the
+/// previous scan. With --dry-run, show the title each rule produces for each
+/// file instead.
+fn scan_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut options = ScanOptions::default();
+    let mut mountpoint: Option<PathBuf> = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--verbose" => options.verbose = true,
+            "--dry-run" | "-n" => options.dry_run = true,
+            "--no-probe" => options.no_probe = true,
+            "--rules" => options.rules = Some(iter.next().ok_or(SCAN_USAGE)?.into()),
+            "--output" | "-o" => options.output = Some(iter.next().ok_or(SCAN_USAGE)?.into()),
+            "--previous" => options.previous = Some(iter.next().ok_or(SCAN_USAGE)?.into()),
+            "--diff-json" => options.diff_json = Some(iter.next().ok_or(SCAN_USAGE)?.into()),
+            _ if arg.starts_with('-') || mountpoint.is_some() => return Err(SCAN_USAGE.into()),
+            _ => mountpoint = Some(arg.into()),
+        }
+    }
+    let mountpoint = mountpoint.ok_or(SCAN_USAGE)?;
+    scan_hdd(&mountpoint, options)?;
+    Ok(())
+}
+
+/// `videofinder duplicates`: list the files found on several disks of the
+/// merged DB (same fingerprint, see fingerprint.rs).
+fn duplicates_command() -> Result<(), Box<dyn Error>> {
+    let groups = Catalog::default().duplicates()?;
+    for group in &groups {
+        for (location, path) in group {
+            println!("{}: {}", location, path);
+        }
+        println!();
+    }
+    println!("{} files found on more than one disk", groups.len());
+    Ok(())
 }
@@ -0,0 +1,323 @@
+//! The catalog browsed rather than searched: the series by season,
+//! the tapes and discs as they are on the shelves, and the folders of the HDDs.
+
+use crate::catalog::{Folder, FolderFile, Season, SerieEpisode, ShelfItem, ShelfRow, SupportRef};
+use crate::enums::SupportType;
+use crate::user_data::ON_LOAN_SQL;
+
+use rusqlite::types::Value;
+use rusqlite::{Connection, Row};
+use std::collections::BTreeMap;
+
+/// The series of the curated films, and those of the HDD episodes (see episode.rs),
+/// "Les Shadoks" and "les shadoks" being the same series.
+pub fn sqlite_series(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT MIN(NAME) FROM \
+           (SELECT SERIE_NAME AS NAME, SERIE_NAME_FOLDED AS FOLDED FROM Film WHERE SERIE_NAME != '' \
+            UNION ALL SELECT SERIE_NAME, SERIE_NAME_FOLDED FROM Tape WHERE SERIE_NAME != '') \
+         GROUP BY FOLDED ORDER BY title_sort_key(MIN(NAME))",
+    )?;
+    stmt.query_map([], |row| row.get(0))?.collect()
+}
+
+/// The season or episode number in column `index`: the Qt DB has an empty
+/// string rather than NULL for some of the films without one.
+fn episode_number(row: &Row, index: usize) -> rusqlite::Result<Option<i32>> {
+    match row.get::<_, Value>(index)? {
+        Value::Text(text) if text.is_empty() => Ok(None),
+        _ => row.get(index),
+    }
+}
+
+pub fn sqlite_seasons(conn: &Connection, serie_name: &str) -> rusqlite::Result<Vec<Season>> {
+    // The curated episodes on each of their supports (if any), then the HDD episodes
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT Film.CODE, Film.NAME, Film.SEASON, Film.EPISODE_NR, \
+           Tape.CODE_TAPE, Tape.TYPE, Tape.LOCATION, Tape.ORIGIN, {on_loan} \
+         FROM Film LEFT JOIN TapeFilm ON TapeFilm.CODE_FILM=Film.CODE \
+           LEFT JOIN Tape ON Tape.CODE_TAPE=TapeFilm.CODE_TAPE \
+         WHERE Film.SERIE_NAME_FOLDED=fold(?1) \
+         UNION ALL \
+         SELECT NULL, Tape.EPISODE_NAME, Tape.SEASON, Tape.EPISODE_NR, \
+           Tape.CODE_TAPE, Tape.TYPE, Tape.LOCATION, Tape.ORIGIN, {on_loan} \
+         FROM Tape WHERE Tape.SERIE_NAME_FOLDED=fold(?1)",
+        on_loan = *ON_LOAN_SQL
+    ))?;
+    let mut seasons: BTreeMap<Option<i32>, Vec<SerieEpisode>> = BTreeMap::new();
+    let mut rows = stmt.query([serie_name])?;
+    while let Some(row) = rows.next()? {
+        let film_code: Option<i32> = row.get(0)?;
+        let name: Option<String> = row.get(1)?;
+        let season = episode_number(row, 2)?;
+        let number = episode_number(row, 3)?;
+        let support = match row.get::<_, Option<i32>>(4)? {
+            Some(code) => Some(SupportRef {
+                code,
+                support_type: row.get::<_, SupportType>(5)?,
+                location: row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+                origin: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+                on_loan: row.get::<_, Option<bool>>(8)?.unwrap_or(false),
+            }),
+            None => None,
+        };
+
+        // The same episode can be on several supports, and both curated and on an HDD
+        let episodes = seasons.entry(season).or_default();
+        let same_episode = |episode: &SerieEpisode| match number {
+            Some(_) => episode.number == number,
+            None => film_code.is_some() && episode.film_code == film_code,
+        };
+        let index = episodes.iter().position(same_episode).unwrap_or_else(|| {
+            episodes.push(SerieEpisode {
+                number,
+                name: String::new(),
+                film_code: None,
+                supports: Vec::new(),
+            });
+            episodes.len() - 1
+        });
+        let episode = &mut episodes[index];
+        if episode.name.is_empty() {
+            episode.name = name.unwrap_or_default();
+        }
+        episode.film_code = episode.film_code.or(film_code);
+        episode.supports.extend(support);
+    }
+
+    let mut seasons: Vec<Season> = seasons
+        .into_iter()
+        .map(|(number, mut episodes)| {
+            episodes.sort_by(|a, b| (a.number, &a.name).cmp(&(b.number, &b.name)));
+            let last = episodes.iter().filter_map(|episode| episode.number).max().unwrap_or(0);
+            let missing = (1..last)
+                .filter(|nr| !episodes.iter().any(|episode| episode.number == Some(*nr)))
+                .collect();
+            Season { number, episodes, missing }
+        })
+        .collect();
+    // The episodes without a season last
+    seasons.sort_by_key(|season| (season.number.is_none(), season.number));
+    Ok(seasons)
+}
+
+pub fn sqlite_shelf_locations(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT DISTINCT LOCATION FROM Tape WHERE LOCATION != '' AND TYPE != ?1 ORDER BY LOCATION",
+    )?;
+    stmt.query_map([SupportType::ComputerFile as i32], |row| row.get(0))?.collect()
+}
+
+pub fn sqlite_shelves(conn: &Connection, location: &str) -> rusqlite::Result<Vec<ShelfRow>> {
+    // HDD files have a shelf, row and position too, but always 1
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT Tape.SHELF, Tape.ROW, Tape.POSITION, Tape.TITLE, MIN(TapeFilm.CODE_FILM), \
+           Tape.CODE_TAPE, Tape.TYPE, Tape.ORIGIN, {} \
+         FROM Tape LEFT JOIN TapeFilm ON TapeFilm.CODE_TAPE=Tape.CODE_TAPE \
+         WHERE Tape.LOCATION=?1 AND Tape.TYPE != ?2 \
+         GROUP BY Tape.CODE_TAPE \
+         ORDER BY Tape.SHELF, Tape.ROW, Tape.POSITION, Tape.TITLE",
+        *ON_LOAN_SQL
+    ))?;
+    let mut rows: Vec<ShelfRow> = Vec::new();
+    let mut query = stmt.query((location, SupportType::ComputerFile as i32))?;
+    while let Some(row) = query.next()? {
+        let shelf = row.get::<_, Option<i32>>(0)?.unwrap_or(0);
+        let row_number = row.get::<_, Option<i32>>(1)?.unwrap_or(0);
+        let item = ShelfItem {
+            position: row.get::<_, Option<i32>>(2)?.unwrap_or(0),
+            title: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+            film_code: row.get(4)?,
+            support: SupportRef {
+                code: row.get(5)?,
+                support_type: row.get(6)?,
+                location: location.to_string(),
+                origin: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+                on_loan: row.get::<_, Option<bool>>(8)?.unwrap_or(false),
+            },
+        };
+        match rows.last_mut().filter(|last| last.shelf == shelf && last.row == row_number) {
+            Some(last) => last.items.push(item),
+            None => rows.push(ShelfRow { shelf, row: row_number, items: vec![item] }),
+        }
+    }
+    Ok(rows)
+}
+
+pub fn sqlite_hdd_locations(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT DISTINCT LOCATION FROM Tape WHERE LOCATION != '' AND TYPE = ?1 ORDER BY LOCATION",
+    )?;
+    stmt.query_map([SupportType::ComputerFile as i32], |row| row.get(0))?.collect()
+}
+
+pub fn sqlite_folder_tree(conn: &Connection, location: &str) -> rusqlite::Result<Folder> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT PATH, TITLE, DURATION, CODE_TAPE, ORIGIN, {} FROM Tape \
+         WHERE LOCATION=?1 AND TYPE=?2 AND PATH != ''",
+        *ON_LOAN_SQL
+    ))?;
+    let mut root = Folder::default();
+    let mut rows = stmt.query((location, SupportType::ComputerFile as i32))?;
+    while let Some(row) = rows.next()? {
+        let path: String = row.get(0)?;
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", &path));
+        let file = FolderFile {
+            name: name.to_string(),
+            title: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            duration: row.get::<_, Option<i32>>(2)?.unwrap_or(0),
+            support: SupportRef {
+                code: row.get(3)?,
+                support_type: SupportType::ComputerFile,
+                location: location.to_string(),
+                origin: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                on_loan: row.get::<_, Option<bool>>(5)?.unwrap_or(false),
+            },
+        };
+        let mut folder = &mut root;
+        for folder_name in dir.split('/').filter(|name| !name.is_empty()) {
+            let index = match folder.folders.iter().position(|sub| sub.name == folder_name) {
+                Some(index) => index,
+                None => {
+                    let path = match folder.path.as_str() {
+                        "" => folder_name.to_string(),
+                        parent => format!("{}/{}", parent, folder_name),
+                    };
+                    let name = folder_name.to_string();
+                    folder.folders.push(Folder { name, path, ..Default::default() });
+                    folder.folders.len() - 1
+                }
+            };
+            folder = &mut folder.folders[index];
+        }
+        folder.files.push(file);
+    }
+    add_up(&mut root);
+    Ok(root)
+}
+
+/// Sort the contents of `folder` and its subfolders, and count their files.
+fn add_up(folder: &mut Folder) {
+    folder.folders.iter_mut().for_each(add_up);
+    folder.folders.sort_by(|a, b| a.name.cmp(&b.name));
+    folder.files.sort_by(|a, b| a.name.cmp(&b.name));
+    folder.file_count = folder.files.len()
+        + folder.folders.iter().map(|subfolder| subfolder.file_count).sum::<usize>();
+    folder.duration = folder.files.iter().map(|file| file.duration).sum::<i32>()
+        + folder.folders.iter().map(|subfolder| subfolder.duration).sum::<i32>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::tests::{hdd_file, merged_db};
+
+    const SHADOKS: &str = "INSERT INTO Tape VALUES (1, 'Les Shadoks 1', 'ARMOIRE_1', 1, 1, 1, NULL, 1, \
+           '', 0, 'E', 0); \
+         INSERT INTO TapeFilm VALUES (1, 1), (1, 2), (1, 3); \
+         INSERT INTO Film VALUES (1, 1968, 2, 1, 1, 1, 'Les Shadoks', 'Le début', NULL, NULL, NULL); \
+         INSERT INTO Film VALUES (2, 1968, 2, 1, 1, 2, 'Les Shadoks', 'La pompe', NULL, NULL, NULL); \
+         INSERT INTO Film VALUES (3, 1968, 2, 1, '', '', 'Les Shadoks', 'Le making of', NULL, NULL, NULL); \
+         INSERT INTO Film VALUES (4, 1968, 2, 1, 1, 5, 'Les Shadoks', 'Le cosmos', NULL, NULL, NULL);";
+
+    fn numbers(season: &Season) -> Vec<Option<i32>> {
+        season.episodes.iter().map(|episode| episode.number).collect()
+    }
+
+    #[test]
+    fn seasons_of_curated_and_hdd_episodes() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            hdd_file("ELORA_9", "Series/Les Shadoks/S01E02.mkv", 2),
+            hdd_file("ELORA_9", "Series/les shadoks/S01E03 - Le Gibi.mkv", 2),
+            hdd_file("ELORA_9", "Series/Les Shadoks/Saison 3/Episode 1.mkv", 2),
+        ];
+        let conn = merged_db(dir.path(), SHADOKS, &files);
+        let seasons = sqlite_seasons(&conn, "LES SHADOKS").unwrap();
+
+        let season_numbers: Vec<_> = seasons.iter().map(|season| season.number).collect();
+        assert_eq!(season_numbers, [Some(1), Some(3), None]);
+        // Episode 2 is both curated and on the HDD
+        assert_eq!(numbers(&seasons[0]), [Some(1), Some(2), Some(3), Some(5)]);
+        let pompe = &seasons[0].episodes[1];
+        assert_eq!((pompe.name.as_str(), pompe.film_code), ("La pompe", Some(2)));
+        let locations: Vec<_> = pompe.supports.iter().map(|s| s.location.as_str()).collect();
+        assert_eq!(locations, ["ARMOIRE_1", "ELORA_9"]);
+        assert_eq!(seasons[0].episodes[2].name, "Le Gibi");
+        assert_eq!(seasons[0].episodes[2].film_code, None);
+        // The empty strings of the Qt DB are no season and no number
+        assert_eq!(numbers(&seasons[2]), [None]);
+        assert_eq!(seasons[2].episodes[0].name, "Le making of");
+
+        assert!(sqlite_seasons(&conn, "Les Shadocks").unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_episodes() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [hdd_file("ELORA_9", "Series/Les Shadoks/Saison 3/Episode 4.mkv", 2)];
+        let conn = merged_db(dir.path(), SHADOKS, &files);
+        let seasons = sqlite_seasons(&conn, "Les Shadoks").unwrap();
+        let missing: Vec<_> = seasons.iter().map(|season| season.missing.clone()).collect();
+        assert_eq!(missing, [vec![3, 4], vec![1, 2, 3], vec![]]);
+    }
+
+    #[test]
+    fn folder_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            hdd_file("ELORA_9", "Series/Les Shadoks/S01E02.mkv", 5),
+            hdd_file("ELORA_9", "Series/Les Shadoks/S01E01.mkv", 4),
+            hdd_file("ELORA_9", "Series/Kaamelott/Saison 1/Episode 1.mkv", 3),
+            hdd_file("ELORA_9", "Films/Playtime.mkv", 120),
+            hdd_file("ELORA_9", "Orfeu Negro.avi", 100),
+            hdd_file("ELORA_3", "Films/Le Mépris.mkv", 102),
+        ];
+        let conn = merged_db(dir.path(), "", &files);
+        let root = sqlite_folder_tree(&conn, "ELORA_9").unwrap();
+
+        assert_eq!((root.name.as_str(), root.path.as_str()), ("", ""));
+        assert_eq!((root.file_count, root.duration), (5, 232));
+        // The file at the root of the disk
+        let root_files: Vec<_> = root.files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(root_files, ["Orfeu Negro.avi"]);
+        let folders: Vec<_> = root.folders.iter().map(|folder| folder.path.as_str()).collect();
+        assert_eq!(folders, ["Films", "Series"]);
+
+        let series = root.find("Series").unwrap();
+        assert_eq!((series.file_count, series.duration), (3, 12));
+        assert!(series.files.is_empty());
+        let shadoks = root.find("Series/Les Shadoks").unwrap();
+        assert_eq!(
+            (shadoks.name.as_str(), shadoks.file_count, shadoks.duration),
+            ("Les Shadoks", 2, 9)
+        );
+        let names: Vec<_> = shadoks.files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, ["S01E01.mkv", "S01E02.mkv"]);
+        assert_eq!(shadoks.files[0].title, "Les Shadoks: S01E01");
+        let season = root.find("Series/Kaamelott/Saison 1/").unwrap();
+        assert_eq!(
+            (season.path.as_str(), season.file_count, season.duration),
+            ("Series/Kaamelott/Saison 1", 1, 3)
+        );
+        assert!(root.find("Series/Kaamelott/Saison 2").is_none());
+
+        let other = sqlite_folder_tree(&conn, "ELORA_3").unwrap();
+        assert_eq!((other.file_count, other.duration), (1, 102));
+        let empty = sqlite_folder_tree(&conn, "ELORA_1").unwrap();
+        assert!(empty.folders.is_empty() && empty.files.is_empty());
+        assert_eq!((empty.file_count, empty.duration), (0, 0));
+    }
+
+    #[test]
+    fn invalid_episode_number_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let rows = format!(
+            "{} INSERT INTO Film VALUES (5, 1968, 2, 1, 'deux', 1, 'Les Shadoks', 'Bis', \
+               NULL, NULL, NULL);",
+            SHADOKS
+        );
+        let conn = merged_db(dir.path(), &rows, &[]);
+        assert!(sqlite_seasons(&conn, "Les Shadoks").is_err());
+    }
+}
@@ -0,0 +1,463 @@
+//! The merged DB, kept open with its prepared statements (see prepare_cached)
+//! instead of being reopened for every search.
+//!
+//! This is the API of the catalog for the app and for other tools, it doesn't depend
+//! on the UI: the types below are converted to the Slint ones in slint_adapter.rs.
+
+use crate::browse::{
+    sqlite_folder_tree, sqlite_hdd_locations, sqlite_seasons, sqlite_series,
+    sqlite_shelf_locations, sqlite_shelves,
+};
+use crate::download;
+use crate::enums::SupportType;
+use crate::fold::register_functions;
+use crate::query::{SearchFilter, SearchQuery, SortOrder, TextField};
+use crate::sqlsearch::{
+    SearchResults, sqlite_duplicates, sqlite_film, sqlite_locations, sqlite_schema_version,
+    sqlite_search, sqlite_support,
+};
+use crate::suggest::{Suggestion, sqlite_suggestions};
+use crate::user_data::{
+    CurrentLoan, Loan, UserData, open_user_db, sqlite_current_loans, sqlite_lend, sqlite_loans,
+    sqlite_return, sqlite_set_user_data, sqlite_user_data,
+};
+use chrono::NaiveDate;
+use rusqlite::{Connection, OpenFlags};
+use std::ops::Range;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Modification time and size of merged.sqlite, to notice that a sync replaced it.
+pub(crate) type FileStamp = Option<(SystemTime, u64)>;
+
+fn file_stamp(path: &Path) -> FileStamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// A person credited for a film
+#[derive(Clone, Debug)]
+pub struct Person {
+    pub name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Film {
+    pub code: i32,
+    pub name: String,
+    /// For TV episodes
+    pub serie_name: Option<String>,
+    pub season: Option<i32>,
+    pub episode_nr: Option<i32>,
+    /// 0 if unknown
+    pub year: i32,
+    /// In minutes, 0 if unknown
+    pub duration: i32,
+    pub director: Option<Person>,
+    pub producer: Option<Person>,
+    pub composer: Option<Person>,
+    pub actors: Vec<Person>,
+    /// Path of the cover on the machine of the Qt app, see image_handling.rs
+    pub image_path: Option<String>,
+}
+
+/// A tape, DVD, Blu-ray or HDD file
+#[derive(Clone, Debug)]
+pub struct Support {
+    pub code: i32,
+    pub support_type: SupportType,
+    pub title: String,
+    pub location: String,
+    pub shelf: i32,
+    pub row: i32,
+    pub position: i32,
+    /// Relative to the HDD's base dir, for HDD files
+    pub path: String,
+    /// In minutes, set by the scanner for HDD files
+    pub duration: i32,
+    /// The slice the row was merged from (merge::QT_SOURCE or an HDD name)
+    pub source: Option<String>,
+    /// When that HDD was scanned, "YYYY-MM-DD"
+    pub scan_date: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// Audio languages, e.g. "fre, eng"
+    pub languages: Option<String>,
+    /// See fingerprint.rs
+    pub fingerprint: Option<String>,
+    /// The same file elsewhere, as (location, path)
+    pub duplicates: Vec<(String, String)>,
+    /// Identifies the support across syncs, for the user data (see user_data.rs)
+    pub key: String,
+}
+
+/// One search result: a support, and the film on it unless grouping by support
+#[derive(Clone, Debug)]
+pub struct SearchHit {
+    /// The film name, "Serie -- Episode (101)" for TV episodes, the support title
+    /// when grouping by support
+    pub name: String,
+    pub film_code: Option<i32>,
+    pub support_code: i32,
+    pub support_type: SupportType,
+    /// Starts with 'E' or 'T' for recorded tapes
+    pub origin: String,
+    pub on_loan: bool,
+    /// When the HDD slice of this row was last merged, if it's a stale copy
+    pub stale_since: Option<i64>,
+    /// Where the searched texts were found, one per text
+    pub matches: Vec<FieldMatch>,
+}
+
+/// A searched text found in a field of a search result, e.g. "depard"
+/// in the actor "Gérard Depardieu"
+#[derive(Clone, Debug)]
+pub struct FieldMatch {
+    pub field: TextField,
+    /// The whole field
+    pub text: String,
+    /// Byte range of the match in `text`
+    pub range: Range<usize>,
+}
+
+/// A support as listed in the browse views
+#[derive(Clone, Debug)]
+pub struct SupportRef {
+    pub code: i32,
+    pub support_type: SupportType,
+    pub location: String,
+    /// Starts with 'E' or 'T' for recorded tapes
+    pub origin: String,
+    pub on_loan: bool,
+}
+
+/// An episode of a series, see Catalog::seasons
+#[derive(Clone, Debug)]
+pub struct SerieEpisode {
+    pub number: Option<i32>,
+    pub name: String,
+    /// None for HDD episodes, which have no Film
+    pub film_code: Option<i32>,
+    /// Where the episode can be found, empty if it's only in the film list
+    pub supports: Vec<SupportRef>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Season {
+    /// None for the episodes without a season number
+    pub number: Option<i32>,
+    /// In episode number order
+    pub episodes: Vec<SerieEpisode>,
+    /// The episode numbers between 1 and the last one that aren't in `episodes`
+    pub missing: Vec<i32>,
+}
+
+/// A tape or disc on a shelf, see Catalog::shelves
+#[derive(Clone, Debug)]
+pub struct ShelfItem {
+    pub position: i32,
+    pub title: String,
+    /// One of the films on the support
+    pub film_code: Option<i32>,
+    pub support: SupportRef,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShelfRow {
+    pub shelf: i32,
+    pub row: i32,
+    /// In position order
+    pub items: Vec<ShelfItem>,
+}
+
+/// A folder of an HDD, see Catalog::folder_tree
+#[derive(Clone, Debug, Default)]
+pub struct Folder {
+    /// Empty for the root folder
+    pub name: String,
+    /// Relative to the HDD's base dir, like Tape.PATH
+    pub path: String,
+    /// In name order, like `files`
+    pub folders: Vec<Folder>,
+    pub files: Vec<FolderFile>,
+    /// Of this folder and its subfolders
+    pub file_count: usize,
+    /// Of this folder and its subfolders, in minutes
+    pub duration: i32,
+}
+
+impl Folder {
+    /// The subfolder at `path`, or this folder for an empty path
+    pub fn find(&self, path: &str) -> Option<&Folder> {
+        path.split('/').filter(|name| !name.is_empty()).try_fold(self, |folder, name| {
+            folder.folders.iter().find(|subfolder| subfolder.name == name)
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FolderFile {
+    /// The file name
+    pub name: String,
+    pub title: String,
+    /// In minutes
+    pub duration: i32,
+    pub support: SupportRef,
+}
+
+#[derive(Default)]
+pub struct Catalog {
+    conn: Option<Connection>,
+    stamp: FileStamp,
+    /// The user DB, to modify it. `conn` reads it too, to filter the searches on it.
+    user_conn: Option<Connection>,
+}
+
+impl Catalog {
+    /// The connection to merged.sqlite, (re)opened if a sync replaced the file
+    /// since last time. merge::merge() renames a new file over the old one rather
+    /// than modifying it, so the open connection keeps reading the old one until then.
+    pub(crate) fn connection(&mut self) -> rusqlite::Result<&Connection> {
+        let path = download::db_full_path();
+        let stamp = file_stamp(&path);
+        let conn = match self.conn.take() {
+            Some(conn) if stamp == self.stamp => conn,
+            _ => {
+                log::info!("Opening {}", path.display());
+                let conn = open(&path)?;
+                self.stamp = stamp;
+                conn
+            }
+        };
+        Ok(self.conn.insert(conn))
+    }
+
+    fn user_connection(&mut self) -> rusqlite::Result<&Connection> {
+        match self.user_conn {
+            Some(ref conn) => Ok(conn),
+            None => Ok(self.user_conn.insert(open_user_db(&download::user_db_full_path())?)),
+        }
+    }
+
+    /// The supports and films matching `query` and `filter`, see hits() to get their details.
+    pub fn search(
+        &mut self,
+        query: &SearchQuery,
+        filter: &SearchFilter,
+        sort_order: SortOrder,
+        group_by_support: bool,
+    ) -> rusqlite::Result<SearchResults> {
+        let mut results =
+            sqlite_search(self.connection()?, query, filter, sort_order, group_by_support)?;
+        results.stamp = self.stamp;
+        Ok(results)
+    }
+
+    /// The results from `offset` to `offset + limit`, or None if a sync replaced
+    /// merged.sqlite since the search: the rows and codes changed, search again.
+    pub fn hits(
+        &mut self,
+        results: &SearchResults,
+        offset: usize,
+        limit: usize,
+    ) -> rusqlite::Result<Option<Vec<SearchHit>>> {
+        self.connection()?;
+        let Some(conn) = self.conn.as_ref().filter(|_| results.stamp == self.stamp) else {
+            return Ok(None);
+        };
+        results.fetch(conn, offset, limit).map(Some)
+    }
+
+    pub fn support(&mut self, support_code: i32) -> rusqlite::Result<Support> {
+        sqlite_support(self.connection()?, support_code)
+    }
+
+    pub fn film(&mut self, film_code: i32) -> rusqlite::Result<Film> {
+        sqlite_film(self.connection()?, film_code)
+    }
+
+    /// The locations of all the supports (shelves and HDD names)
+    pub fn locations(&mut self) -> rusqlite::Result<Vec<String>> {
+        sqlite_locations(self.connection()?)
+    }
+
+    /// The series, titles and people to suggest while `text` is typed, at most `limit`
+    pub fn suggestions(&mut self, text: &str, limit: usize) -> rusqlite::Result<Vec<Suggestion>> {
+        sqlite_suggestions(self.connection()?, text, limit)
+    }
+
+    /// The names of all the series, curated ones and HDD ones, in title order
+    pub fn series(&mut self) -> rusqlite::Result<Vec<String>> {
+        sqlite_series(self.connection()?)
+    }
+
+    /// The episodes of the series `serie_name`, by season
+    pub fn seasons(&mut self, serie_name: &str) -> rusqlite::Result<Vec<Season>> {
+        sqlite_seasons(self.connection()?, serie_name)
+    }
+
+    /// The locations with tapes or discs, unlike the HDDs
+    pub fn shelf_locations(&mut self) -> rusqlite::Result<Vec<String>> {
+        sqlite_shelf_locations(self.connection()?)
+    }
+
+    /// The rows of all the shelves at `location`, in shelf and row order
+    pub fn shelves(&mut self, location: &str) -> rusqlite::Result<Vec<ShelfRow>> {
+        sqlite_shelves(self.connection()?, location)
+    }
+
+    /// The locations with HDD files
+    pub fn hdd_locations(&mut self) -> rusqlite::Result<Vec<String>> {
+        sqlite_hdd_locations(self.connection()?)
+    }
+
+    /// The folders of the HDD at `location`, from the paths of its files
+    pub fn folder_tree(&mut self, location: &str) -> rusqlite::Result<Folder> {
+        sqlite_folder_tree(self.connection()?, location)
+    }
+
+    /// What we recorded about the film `film_code` (None for the support itself)
+    /// on the support with the key `support_key` (see Support::key)
+    pub fn user_data(
+        &mut self,
+        film_code: Option<i32>,
+        support_key: &str,
+    ) -> rusqlite::Result<UserData> {
+        sqlite_user_data(self.user_connection()?, film_code.unwrap_or(0), support_key)
+    }
+
+    pub fn set_user_data(
+        &mut self,
+        film_code: Option<i32>,
+        support_key: &str,
+        data: &UserData,
+    ) -> rusqlite::Result<()> {
+        sqlite_set_user_data(self.user_connection()?, film_code.unwrap_or(0), support_key, data)
+    }
+
+    /// The loans of the support with the key `support_key`, the current one
+    /// (if any) first, then the latest ones.
+    pub fn loans(&mut self, support_key: &str) -> rusqlite::Result<Vec<Loan>> {
+        sqlite_loans(self.user_connection()?, support_key)
+    }
+
+    /// Record that the support with the key `support_key` was lent today.
+    pub fn lend(
+        &mut self,
+        support_key: &str,
+        borrower: &str,
+        due_on: Option<NaiveDate>,
+    ) -> rusqlite::Result<()> {
+        sqlite_lend(self.user_connection()?, support_key, borrower, due_on)
+    }
+
+    /// Record that the support with the key `support_key` came back today.
+    pub fn return_support(&mut self, support_key: &str) -> rusqlite::Result<()> {
+        sqlite_return(self.user_connection()?, support_key)
+    }
+
+    /// The supports currently lent, the ones expected back first first
+    pub fn current_loans(&mut self) -> rusqlite::Result<Vec<CurrentLoan>> {
+        sqlite_current_loans(self.connection()?)
+    }
+
+    /// Layout version of the merged DB, see merge::MERGED_SCHEMA_VERSION
+    pub fn schema_version(&mut self) -> rusqlite::Result<i32> {
+        sqlite_schema_version(self.connection()?)
+    }
+
+    /// Files found in more than one location, as groups of (location, path)
+    pub fn duplicates(&mut self) -> rusqlite::Result<Vec<Vec<(String, String)>>> {
+        sqlite_duplicates(self.connection()?)
+    }
+}
+
+fn open(path: &Path) -> rusqlite::Result<Connection> {
+    open_with_user_db(path, &download::user_db_full_path())
+}
+
+fn open_with_user_db(path: &Path, user_db_path: &Path) -> rusqlite::Result<Connection> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    register_functions(&conn)?;
+    // Read-only like the main DB, for the user data filters (see SearchFilter)
+    open_user_db(user_db_path)?; // creates it the first time
+    conn.execute("ATTACH DATABASE ?1 AS userdb", [user_db_path.to_string_lossy()])?;
+    // Only read from: map up to 256 MB of it, and cache up to 16 MB of pages
+    conn.execute_batch("PRAGMA mmap_size = 268435456; PRAGMA cache_size = -16384;")?;
+    Ok(conn)
+}
+
+/// A merged DB to test the queries on, see merged_db()
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::merge::{Slice, TapeRow, merge};
+    use crate::scan_rules::Rules;
+    use std::collections::BTreeSet;
+
+    /// The tables of the Qt DB, with the columns the merge and the app use
+    const QT_TABLES: &str = "CREATE TABLE Tape (CODE_TAPE INTEGER PRIMARY KEY, TITLE TEXT, \
+           LOCATION TEXT, SHELF INT, ROW INT, POSITION INT, PATH TEXT, TYPE INT, \
+           DATE_PURCHASE TEXT, DURATION INT, ORIGIN TEXT, ON_LOAN INT); \
+         CREATE TABLE TapeFilm (CODE_TAPE INT, CODE_FILM INT); \
+         CREATE TABLE Film (CODE INTEGER PRIMARY KEY, YEAR INT, DURATION INT, TYPE INT, \
+           SEASON, EPISODE_NR, SERIE_NAME TEXT, NAME TEXT, DIRECTOR TEXT, PRODUCER TEXT, \
+           COMPOSER TEXT); \
+         CREATE TABLE Actor (CODE_FILM INT, ACTOR TEXT);";
+
+    /// An HDD file as the scanner would list it, titled by the default rules
+    pub(crate) fn hdd_file(location: &str, path: &str, duration: i32) -> TapeRow {
+        let all_paths = BTreeSet::from([path.to_string()]);
+        let shaped = Rules::load(None).unwrap().shape(path, &all_paths, None).unwrap();
+        TapeRow {
+            path: path.to_string(),
+            title: shaped.title,
+            location: location.to_string(),
+            shelf: 1,
+            row: 1,
+            position: 1,
+            type_: SupportType::ComputerFile as i32,
+            date_purchase: String::new(),
+            duration,
+            width: None,
+            height: None,
+            languages: Vec::new(),
+            size: None,
+            fingerprint: None,
+        }
+    }
+
+    /// Merge in `dir` the Qt DB made of `qt_rows` (INSERT statements) and one slice
+    /// per location of `hdd_files`, then open it the way the app does, along with
+    /// the user DB (`dir`/user.sqlite).
+    pub(crate) fn merged_db(dir: &Path, qt_rows: &str, hdd_files: &[TapeRow]) -> Connection {
+        let qt_db = dir.join("qt.sqlite");
+        Connection::open(&qt_db)
+            .unwrap()
+            .execute_batch(&format!("{} {}", QT_TABLES, qt_rows))
+            .unwrap();
+        let locations: BTreeSet<&str> =
+            hdd_files.iter().map(|file| file.location.as_str()).collect();
+        let slices: Vec<Slice> = locations
+            .into_iter()
+            .map(|location| {
+                let path = dir.join(format!("{}.jsonl", location));
+                let lines: Vec<String> = hdd_files
+                    .iter()
+                    .filter(|file| file.location == location)
+                    .map(|file| serde_json::to_string(file).unwrap())
+                    .collect();
+                std::fs::write(&path, lines.join("\n")).unwrap();
+                Slice { name: location.to_string(), path, stale_since: None }
+            })
+            .collect();
+        let merged_db = dir.join("merged.sqlite");
+        merge(&qt_db, &slices, &merged_db).unwrap();
+        open_with_user_db(&merged_db, &user_db(dir)).unwrap()
+    }
+
+    /// The user DB of merged_db(), to modify it
+    pub(crate) fn user_db(dir: &Path) -> std::path::PathBuf {
+        dir.join("user.sqlite")
+    }
+}
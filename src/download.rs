@@ -1,9 +1,13 @@
 use std::fs::File;
+#[cfg(feature = "ui")]
 use std::io::BufRead;
+#[cfg(feature = "ui")]
 use std::io::BufReader;
 use std::io::Write;
 use std::path::PathBuf;
 
+use crate::merge::Slice;
+
 fn db_dir() -> PathBuf {
     if cfg!(target_os = "android") {
         //PathBuf::from("/storage/emulated/0/Download")
@@ -14,7 +18,7 @@ fn db_dir() -> PathBuf {
 }
 
 /// HDDs whose JSONL slices should be merged into the queryable DB.
-/// Each name must match the LOCATION label produced by scripts/scan_hdd.py
+/// Each name must match the LOCATION label produced by `videofinder scan`
 /// (derived from the HDD's `id` file). The corresponding files on the FTP
 /// server are `<NAME>.jsonl`, served alongside kvideomanager.sqlite.
 pub const HDD_NAMES: &[&str] = &["ELORA_1", "ELORA_2", "ELORA_3"];
@@ -30,10 +34,21 @@ pub fn db_full_path() -> PathBuf {
     db_dir().join("merged.sqlite")
 }
 
+/// Path to our own data (favourites, ratings...), which a sync leaves alone.
+pub fn user_db_full_path() -> PathBuf {
+    db_dir().join("videofinder-user.sqlite")
+}
+
 pub fn jsonl_full_path(hdd_name: &str) -> PathBuf {
     db_dir().join(format!("{}.jsonl", hdd_name))
 }
 
+/// Last successfully merged copy of an HDD slice, reused when the next
+/// download of that slice fails.
+pub fn jsonl_cache_full_path(hdd_name: &str) -> PathBuf {
+    db_dir().join(format!("{}.last.jsonl", hdd_name))
+}
+
 pub fn filelist_full_path() -> PathBuf {
     db_dir().join("filelist.txt")
 }
@@ -76,7 +91,9 @@ pub async fn download_to_file(
     Ok(())
 }
 
+#[cfg(feature = "ui")]
 pub type ImageForDirHash = std::collections::HashMap<PathBuf, PathBuf>;
+#[cfg(feature = "ui")]
 pub fn parse_file_list() -> Result<ImageForDirHash, anyhow::Error> {
     log::debug!("parse_file_list");
     let file = File::open(filelist_full_path())?;
@@ -117,26 +134,58 @@ pub async fn download_db(progress_func: Box<ProgressFunc>) -> Result<(), anyhow:
     download_to_file(&filelist_url, filelist_full_path(), dummy_fn).await?;
 
     // HDD slices: download each one, but a missing/unreachable file just
-    // produces a warning — the merge step will skip whatever isn't present.
-    let mut jsonl_paths: Vec<PathBuf> = Vec::new();
+    // produces a warning, and we fall back to the last copy that was merged
+    // successfully (marked as stale), so the HDD doesn't vanish from search.
+    let mut slices: Vec<Slice> = Vec::new();
     for hdd in HDD_NAMES {
         let url = format!("{}/{}.jsonl", BASE_URL, hdd);
         let path = jsonl_full_path(hdd);
         match download_to_file(&url, path.clone(), Box::new(|_| {})).await {
-            Ok(()) => jsonl_paths.push(path),
-            Err(e) => log::warn!("Failed to download {}: {}", url, e),
+            Ok(()) => slices.push(Slice { name: hdd.to_string(), path, stale_since: None }),
+            Err(e) => {
+                log::warn!("Failed to download {}: {}", url, e);
+                let cache_path = jsonl_cache_full_path(hdd);
+                match modification_time(&cache_path) {
+                    Some(cached_at) => {
+                        log::info!("Using last known copy {}", cache_path.display());
+                        slices.push(Slice {
+                            name: hdd.to_string(),
+                            path: cache_path,
+                            stale_since: Some(cached_at),
+                        });
+                    }
+                    None => log::warn!("No cached copy of {} either, skipping it", hdd),
+                }
+            }
         }
     }
 
-    crate::merge::merge(&qt_path, &jsonl_paths, &db_full_path())?;
+    crate::merge::merge(&qt_path, &slices, &db_full_path())?;
+
+    // The fresh slices are now known to merge fine, keep them for next time.
+    for slice in slices.iter().filter(|slice| slice.stale_since.is_none()) {
+        if let Err(e) = std::fs::copy(&slice.path, jsonl_cache_full_path(&slice.name)) {
+            log::warn!("Failed to cache {}: {}", slice.path.display(), e);
+        }
+    }
     Ok(())
 }
 
+/// Seconds since the epoch at which `path` was last written, None if it doesn't exist.
+fn modification_time(path: &Path) -> Option<i64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_secs() as i64)
+}
+
+#[cfg(feature = "ui")]
 use slint::Image;
 use std::path::Path;
+#[cfg(feature = "ui")]
 use tempfile::Builder;
 
 /// Downloads the image at `url_str`, writes it to a temp file, and loads it via `slint::Image::load_from_path`.
+#[cfg(feature = "ui")]
 pub async fn download_image_data(url_str: &str) -> Result<Image, anyhow::Error> {
     log::info!("Downloading image from {}", url_str);
 
@@ -0,0 +1,137 @@
+//! Series, season and episode of the HDD files stored under the series folders
+//! (`Series/<Name>/` with the default scanner rules, see the `"series"` rule),
+//! so that they can be displayed and sorted like the curated TV episodes.
+
+use crate::scan_rules::Rules;
+use regex::Regex;
+use std::sync::LazyLock;
+
+// S01E02, s1e2, S01 E02
+static SEASON_EPISODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bS(\d{1,2})\s*E(\d{1,3})\b").unwrap());
+// 1x02 (but not 1920x1080)
+static NUMBER_X_NUMBER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(\d{1,2})x(\d{2,3})\b").unwrap());
+// Saison 1/Episode 2.mkv
+static SEASON_DIR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)(?:^|/)(?:saison|season)\s*(\d{1,2})/").unwrap());
+static EPISODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b[ée]pisode\s*(\d{1,3})\b").unwrap());
+
+pub struct Episode {
+    pub serie_name: String,
+    pub season: Option<i32>,
+    pub episode_nr: Option<i32>,
+    /// What's left of the title once the series and episode number are removed
+    pub name: Option<String>,
+}
+
+fn number(caps: &regex::Captures, index: usize) -> Option<i32> {
+    caps.get(index)?.as_str().parse().ok()
+}
+
+fn trim(text: &str) -> &str {
+    text.trim_matches(|c: char| c.is_whitespace() || "-._".contains(c))
+}
+
+/// The episode name around the episode number: usually after it
+/// ("S01E02 - Pilot"), the part before often being the series name again.
+fn around<'a>(title: &'a str, caps: &regex::Captures) -> &'a str {
+    let whole = caps.get(0).unwrap();
+    let after = trim(&title[whole.end()..]);
+    if after.is_empty() { trim(&title[..whole.start()]) } else { after }
+}
+
+/// Parse the HDD file at `path` (relative to the HDD's base dir), whose title
+/// was shaped by the scanner `rules` into `title`. Returns None if it's not
+/// under a series folder.
+pub fn parse_episode(rules: &Rules, path: &str, title: &str) -> Option<Episode> {
+    let series = rules.series(path)?;
+    let rest = series.rest;
+    // The series rule prefixes the title, with "<Name>: " by default
+    let title = title.strip_prefix(&series.title_prefix).unwrap_or(title);
+
+    let (season, episode_nr, name) = if let Some(caps) =
+        SEASON_EPISODE.captures(title).or_else(|| NUMBER_X_NUMBER.captures(title))
+    {
+        (number(&caps, 1), number(&caps, 2), around(title, &caps))
+    } else if let Some(caps) = EPISODE.captures(title) {
+        let season = SEASON_DIR.captures(rest).and_then(|caps| number(&caps, 1));
+        (season, number(&caps, 1), around(title, &caps))
+    } else {
+        (None, None, trim(title))
+    };
+
+    Some(Episode {
+        serie_name: series.name,
+        season,
+        episode_nr,
+        name: (!name.is_empty()).then(|| name.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Series, season, episode number and name
+    type Parsed = (String, Option<i32>, Option<i32>, Option<String>);
+
+    fn parse(path: &str, title: &str) -> Option<Parsed> {
+        let episode = parse_episode(&Rules::load(None).unwrap(), path, title)?;
+        Some((episode.serie_name, episode.season, episode.episode_nr, episode.name))
+    }
+
+    #[test]
+    fn season_and_episode_in_the_title() {
+        assert_eq!(
+            parse(
+                "Series/Les Shadoks/S01E02 - Il faut pomper.mkv",
+                "Les Shadoks: S01E02 - Il faut pomper"
+            ),
+            Some(("Les Shadoks".to_string(), Some(1), Some(2), Some("Il faut pomper".to_string())))
+        );
+        assert_eq!(
+            parse("Series/Columbo/Columbo 1x02.avi", "Columbo: Columbo 1x02"),
+            Some(("Columbo".to_string(), Some(1), Some(2), Some("Columbo".to_string())))
+        );
+        // Not an episode number
+        assert_eq!(
+            parse("Series/Columbo/Making of 1920x1080.mkv", "Columbo: Making of 1920x1080"),
+            Some(("Columbo".to_string(), None, None, Some("Making of 1920x1080".to_string())))
+        );
+    }
+
+    #[test]
+    fn season_from_the_folder() {
+        assert_eq!(
+            parse("Series/Kaamelott/Saison 2/Episode 5.mkv", "Kaamelott: Episode 5"),
+            Some(("Kaamelott".to_string(), Some(2), Some(5), None))
+        );
+        // No season folder: only the episode number
+        assert_eq!(
+            parse(
+                "Series/Kaamelott/Épisode 7 - Le Sanglier.mkv",
+                "Kaamelott: Épisode 7 - Le Sanglier"
+            ),
+            Some(("Kaamelott".to_string(), None, Some(7), Some("Le Sanglier".to_string())))
+        );
+    }
+
+    #[test]
+    fn series_folder_from_the_rules() {
+        assert!(parse("Films/Les Shadoks/S01E01.mkv", "S01E01").is_none());
+        assert!(parse("Series/S01E01.mkv", "S01E01").is_none());
+        let json = r#"{
+            "extensions": ["mkv"],
+            "rules": [{"prefix": "^TV/([^/]+)/", "with": "${1} - ", "series": true}]
+        }"#;
+        let rules = Rules::parse(json).unwrap();
+        let episode = parse_episode(&rules, "TV/Columbo/S02E03.mkv", "Columbo - S02E03").unwrap();
+        assert_eq!(
+            (episode.serie_name.as_str(), episode.season, episode.episode_nr),
+            ("Columbo", Some(2), Some(3))
+        );
+        assert!(parse_episode(&rules, "Series/Columbo/S02E03.mkv", "S02E03").is_none());
+    }
+}
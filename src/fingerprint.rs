@@ -0,0 +1,30 @@
+//! Fast content identity for video files: the size, plus hashes of the
+//! first, middle and last MiB. Reading 3 MiB per file keeps a scan fast even
+//! on a USB disk, and is enough to recognize the same recording on several
+//! disks, or a renamed file.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const CHUNK_SIZE: u64 = 1024 * 1024;
+
+fn hash_chunk(file: &mut File, offset: u64) -> Option<u64> {
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE as usize);
+    file.take(CHUNK_SIZE).read_to_end(&mut chunk).ok()?;
+    Some(xxhash_rust::xxh3::xxh3_64(&chunk))
+}
+
+/// Returns the size of the file and its fingerprint, formatted as
+/// "<size in hex>-<first>-<middle>-<last>" so that it can be compared as a
+/// whole. Small files get overlapping chunks, which is fine.
+pub fn fingerprint(path: &Path) -> Option<(u64, String)> {
+    let mut file = File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    let last = size.saturating_sub(CHUNK_SIZE);
+    let first_hash = hash_chunk(&mut file, 0)?;
+    let middle_hash = hash_chunk(&mut file, last / 2)?;
+    let last_hash = hash_chunk(&mut file, last)?;
+    Some((size, format!("{:x}-{:016x}-{:016x}-{:016x}", size, first_hash, middle_hash, last_hash)))
+}
@@ -0,0 +1,109 @@
+//! Text normalization for search: "Œil", "oeil" and "OEIL" must all match,
+//! as well as "Été" and "ete". SQLite's LIKE only folds ASCII case, so the
+//! merged DB stores a folded copy of every searchable field (see merge.rs),
+//! and the query is folded the same way.
+
+use rusqlite::Connection;
+use rusqlite::functions::FunctionFlags;
+use std::ops::Range;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+/// Lowercase, strip accents, and expand ligatures.
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    // NFKD splits "é" into "e" + combining accent, and "ﬁ" into "fi"
+    for c in text.nfkd().flat_map(char::to_lowercase).filter(|c| !is_combining_mark(*c)) {
+        match c {
+            'œ' => folded.push_str("oe"),
+            'æ' => folded.push_str("ae"),
+            'ß' => folded.push_str("ss"),
+            _ => folded.push(c),
+        }
+    }
+    folded
+}
+
+/// Byte range in `text` of the folded `needle`, e.g. "Gérard" for "gerard"
+/// in "Gérard Depardieu".
+pub fn find_folded(text: &str, needle: &str) -> Option<Range<usize>> {
+    // Fold one character at a time, remembering which character each folded byte comes from
+    let mut folded = String::with_capacity(text.len());
+    let mut origins: Vec<Range<usize>> = Vec::with_capacity(text.len());
+    for (start, c) in text.char_indices() {
+        folded.push_str(&fold(c.encode_utf8(&mut [0; 4])));
+        origins.resize(folded.len(), start..start + c.len_utf8());
+    }
+    let start = folded.find(needle)?;
+    let last = (start + needle.len()).checked_sub(1)?;
+    Some(origins.get(start)?.start..origins.get(last)?.end)
+}
+
+/// Sort key for titles: folded, without the leading article,
+/// so that "Le Mépris" sorts among the M's.
+pub fn title_sort_key(title: &str) -> String {
+    let folded = fold(title);
+    for article in ["le ", "la ", "les ", "l'", "l\u{2019}", "the ", "a "] {
+        if let Some(rest) = folded.strip_prefix(article) {
+            return rest.trim_start().to_string();
+        }
+    }
+    folded
+}
+
+/// Make `fold(text)` and `title_sort_key(text)` available in SQL statements on `conn`.
+pub fn register_functions(conn: &Connection) -> rusqlite::Result<()> {
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    conn.create_scalar_function("fold", 1, flags, |ctx| {
+        Ok(ctx.get::<Option<String>>(0)?.map(|text| fold(&text)))
+    })?;
+    conn.create_scalar_function("title_sort_key", 1, flags, |ctx| {
+        Ok(ctx.get::<Option<String>>(0)?.map(|text| title_sort_key(&text)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fold_ligatures_and_accents() {
+        for text in ["Œil", "oeil", "OEIL", "œil"] {
+            assert_eq!(fold(text), "oeil", "{}", text);
+        }
+        assert_eq!(fold("Été"), "ete");
+        assert_eq!(fold("ete"), "ete");
+        assert_eq!(fold("Ægypte, Straße, ﬁn"), "aegypte, strasse, fin");
+    }
+
+    #[test]
+    fn find_folded_ranges() {
+        let text = "Gérard Depardieu";
+        assert_eq!(find_folded(text, "gerard").map(|range| &text[range]), Some("Gérard"));
+        // Longer once folded: the range covers the whole ligature
+        let text = "Le Cœur fidèle";
+        assert_eq!(find_folded(text, "coeur").map(|range| &text[range]), Some("Cœur"));
+        assert_eq!(find_folded(text, "oe").map(|range| &text[range]), Some("œ"));
+        let text = "Le ﬁlm";
+        assert_eq!(find_folded(text, "film").map(|range| &text[range]), Some("ﬁlm"));
+        // Matches after an expansion still point at the right bytes
+        let text = "Maßstab Œuvre";
+        assert_eq!(find_folded(text, "strasse"), None);
+        assert_eq!(find_folded(text, "massstab").map(|range| &text[range]), Some("Maßstab"));
+        assert_eq!(find_folded(text, "oeuvre").map(|range| &text[range]), Some("Œuvre"));
+        assert_eq!(find_folded(text, "xyz"), None);
+        assert_eq!(find_folded(text, ""), None);
+    }
+
+    #[test]
+    fn title_sort_key_without_article() {
+        assert_eq!(title_sort_key("Le Mépris"), "mepris");
+        assert_eq!(title_sort_key("L'Atalante"), "atalante");
+        assert_eq!(title_sort_key("L\u{2019}Atalante"), "atalante");
+        assert_eq!(title_sort_key("Les Enfants du paradis"), "enfants du paradis");
+        assert_eq!(title_sort_key("The Kid"), "kid");
+        // Not an article
+        assert_eq!(title_sort_key("Lenny"), "lenny");
+        assert_eq!(title_sort_key("Laura"), "laura");
+    }
+}
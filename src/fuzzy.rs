@@ -0,0 +1,100 @@
+//! Typo-tolerant fallback for the search: when nothing contains the query,
+//! look for the titles and person names (see the SearchTerm table in merge.rs)
+//! that are within a few typos of it, "scorcese" finding "scorsese".
+
+/// Maximum number of corrected terms to search for.
+const MAX_TERMS: usize = 10;
+
+/// Levenshtein distance between `a` and `b`, in characters.
+pub fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// How many typos are tolerated in a query of `len` characters:
+/// none below 4 characters, otherwise one per 4 characters.
+fn max_distance(len: usize) -> usize {
+    len / 4
+}
+
+/// The part of `candidate` closest to `query`, with its distance, if close enough.
+/// `query` is compared with every run of as many consecutive words in `candidate`,
+/// so that "belmondi" finds "belmondo" in "jean-paul belmondo".
+fn closest_part(query: &[char], query_words: usize, candidate: &str) -> Option<(usize, String)> {
+    let max = max_distance(query.len());
+    let words: Vec<&str> = candidate.split_whitespace().collect();
+    words
+        .windows(query_words.min(words.len()).max(1))
+        .filter_map(|window| {
+            // "shadoks:" in "les shadoks: s01e03"
+            let part =
+                window.join(" ").trim_matches(|c: char| c.is_ascii_punctuation()).to_string();
+            let chars: Vec<char> = part.chars().collect();
+            if chars.len().abs_diff(query.len()) > max {
+                return None;
+            }
+            let distance = edit_distance(query, &chars);
+            (distance <= max).then_some((distance, part))
+        })
+        .min()
+}
+
+/// The terms close to the (folded) `query` among `candidates`, closest first.
+pub fn closest_terms(query: &str, candidates: impl Iterator<Item = String>) -> Vec<String> {
+    let query_chars: Vec<char> = query.chars().collect();
+    if max_distance(query_chars.len()) == 0 {
+        return Vec::new();
+    }
+    let query_words = query.split_whitespace().count();
+    let mut matches: Vec<(usize, String)> = candidates
+        .filter_map(|candidate| closest_part(&query_chars, query_words, &candidate))
+        .collect();
+    matches.sort();
+    matches.dedup_by(|a, b| a.1 == b.1);
+    matches.into_iter().take(MAX_TERMS).map(|(_, term)| term).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates() -> impl Iterator<Item = String> {
+        ["le mepris", "martin scorsese", "jean-paul belmondo", "les shadoks: s01e03"]
+            .into_iter()
+            .map(String::from)
+    }
+
+    #[test]
+    fn distance() {
+        let chars = |text: &str| text.chars().collect::<Vec<_>>();
+        assert_eq!(edit_distance(&chars("mepris"), &chars("mepris")), 0);
+        assert_eq!(edit_distance(&chars("mepris"), &chars("mapris")), 1);
+        assert_eq!(edit_distance(&chars("mepris"), &chars("mepri")), 1);
+        assert_eq!(edit_distance(&chars("œil"), &chars("oil")), 1);
+        assert_eq!(edit_distance(&chars(""), &chars("abc")), 3);
+    }
+
+    #[test]
+    fn one_typo_finds_the_title() {
+        assert_eq!(closest_terms("le mapris", candidates()), ["le mepris"]);
+        assert_eq!(closest_terms("scorcese", candidates()), ["scorsese"]);
+        assert_eq!(closest_terms("belmondi", candidates()), ["belmondo"]);
+        assert_eq!(closest_terms("shadocks", candidates()), ["shadoks"]);
+    }
+
+    #[test]
+    fn unrelated_word_finds_nothing() {
+        assert!(closest_terms("kurosawa", candidates()).is_empty());
+        // Too short to tolerate a typo
+        assert!(closest_terms("lez", candidates()).is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::AppWindow;
+use crate::app::AppWindow;
 use crate::download::download_image_data;
 use anyhow::anyhow;
 use std::cell::RefCell;
@@ -1,49 +1,131 @@
+use crate::episode::parse_episode;
+use crate::fold::register_functions;
+use crate::scan_rules::Rules;
 use anyhow::Context;
 use rusqlite::Connection;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-/// One Tape row as produced by scripts/scan_hdd.py. CODE_TAPE is assigned
-/// at merge time, not by the scanner.
-#[derive(Deserialize)]
-struct TapeRow {
-    path: String,
-    title: String,
-    location: String,
-    shelf: i32,
-    row: i32,
-    position: i32,
+/// One Tape row as produced by the HDD scanner (`videofinder scan`, or the
+/// older scripts/scan_hdd.py). CODE_TAPE is assigned at merge time, not by
+/// the scanner. The field order is the order of the keys in the JSONL.
+#[derive(Serialize, Deserialize)]
+pub struct TapeRow {
+    pub path: String,
+    pub title: String,
+    pub location: String,
+    pub shelf: i32,
+    pub row: i32,
+    pub position: i32,
     #[serde(rename = "type")]
-    type_: i32,
-    date_purchase: String,
-    duration: i32,
+    pub type_: i32,
+    pub date_purchase: String,
+    pub duration: i32,
+    // Read from the container by the native scanner (see probe.rs). Omitted
+    // when unknown, so that a scan without probing matches scan_hdd.py's output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
+    // File size and content fingerprint (see fingerprint.rs), to recognize a
+    // renamed file or the same file on several disks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+}
+
+/// Optional first line of a JSONL slice, describing the scan that produced it:
+/// `{"meta": {"scan_date": "2026-09-03", "scanner": "scan_hdd.py 1"}}`
+#[derive(Serialize, Deserialize)]
+pub struct SliceMeta {
+    pub scan_date: String,
+    pub scanner: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SliceLine {
+    Meta { meta: SliceMeta },
+    Row(TapeRow),
+}
+
+/// Value of Tape.SOURCE for the rows coming from the Qt DB. Rows coming from
+/// an HDD slice have the slice name there instead.
+pub const QT_SOURCE: &str = "qt";
+
+/// Bumped whenever merge() changes the layout of the merged DB, so that a DB
+/// produced by an older version can be detected (see PRAGMA user_version).
+pub const MERGED_SCHEMA_VERSION: i32 = 9;
+
+/// One HDD slice to merge: the JSONL file for the HDD called `name`.
+pub struct Slice {
+    pub name: String,
+    pub path: PathBuf,
+    /// None for a freshly downloaded slice. When the download failed and we
+    /// fell back to the last successfully merged copy, this is the time
+    /// (in seconds since the epoch) that copy was cached.
+    pub stale_since: Option<i64>,
+}
+
+/// Read the metadata (if any) and the rows of a JSONL slice.
+pub fn read_slice(jsonl_path: &Path) -> Result<(Option<SliceMeta>, Vec<TapeRow>), anyhow::Error> {
+    let f = File::open(jsonl_path).with_context(|| format!("opening {}", jsonl_path.display()))?;
+    let reader = BufReader::new(f);
+    let mut meta: Option<SliceMeta> = None;
+    let mut rows = Vec::new();
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line
+            .with_context(|| format!("reading line {} of {}", line_no + 1, jsonl_path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line)
+            .with_context(|| format!("parsing line {} of {}", line_no + 1, jsonl_path.display()))?
+        {
+            SliceLine::Meta { meta: m } => meta = Some(m),
+            SliceLine::Row(row) => rows.push(row),
+        }
+    }
+    Ok((meta, rows))
 }
 
 /// Produce `merged_db` by copying the Qt-curated `qt_db` and appending the
-/// HDD Tape rows from each JSONL file. Missing JSONL files are warned about
+/// HDD Tape rows from each JSONL slice. Missing JSONL files are warned about
 /// and skipped, so a partial source set still yields a usable DB.
-pub fn merge(
-    qt_db: &Path,
-    jsonl_paths: &[std::path::PathBuf],
-    merged_db: &Path,
-) -> Result<(), anyhow::Error> {
+/// Every Tape row records where it came from in Tape.SOURCE, and the Slice
+/// table records the metadata of each merged slice (scan date, scanner,
+/// row count, and whether it's a stale copy).
+pub fn merge(qt_db: &Path, slices: &[Slice], merged_db: &Path) -> Result<(), anyhow::Error> {
     log::info!(
         "Merging {} into {} (+ {} HDD slice(s))",
         qt_db.display(),
         merged_db.display(),
-        jsonl_paths.len()
+        slices.len()
     );
 
-    // Remove any stale merged DB before copying, so a half-written previous
-    // run can't leak across.
-    if merged_db.exists() {
-        fs::remove_file(merged_db).context("removing stale merged DB")?;
+    // Written next to merged_db, then renamed over it once complete, so that the app
+    // (see Catalog::connection) never opens a half-merged DB. Remove any leftover
+    // of a failed previous run before copying, so it can't leak across.
+    let tmp_db = {
+        let mut name = merged_db.as_os_str().to_owned();
+        name.push(".tmp");
+        PathBuf::from(name)
+    };
+    if tmp_db.exists() {
+        fs::remove_file(&tmp_db).context("removing stale temporary merged DB")?;
     }
-    fs::copy(qt_db, merged_db).context("copying Qt DB to merged path")?;
+    fs::copy(qt_db, &tmp_db).context("copying Qt DB to merged path")?;
 
-    let mut conn = Connection::open(merged_db).context("opening merged DB")?;
+    let mut conn = Connection::open(&tmp_db).context("opening merged DB")?;
+
+    // The series folders of the default scanner rules (the slices don't say which rules
+    // they were scanned with)
+    let rules = Rules::load(None).context("loading the default scan rules")?;
 
     let tx = conn.transaction().context("starting transaction")?;
     let mut inserted: usize = 0;
@@ -52,31 +134,55 @@ pub fn merge(
             .query_row("SELECT COALESCE(MAX(CODE_TAPE), 0) + 1 FROM Tape", [], |row| row.get(0))
             .context("reading max CODE_TAPE")?;
 
+        tx.execute("ALTER TABLE Tape ADD COLUMN SOURCE TEXT", []).context("adding SOURCE")?;
+        tx.execute("UPDATE Tape SET SOURCE=?1", [QT_SOURCE]).context("setting SOURCE")?;
+        tx.execute_batch(
+            "ALTER TABLE Tape ADD COLUMN WIDTH INTEGER; \
+             ALTER TABLE Tape ADD COLUMN HEIGHT INTEGER; \
+             ALTER TABLE Tape ADD COLUMN LANGUAGES TEXT; \
+             ALTER TABLE Tape ADD COLUMN SIZE INTEGER; \
+             ALTER TABLE Tape ADD COLUMN FINGERPRINT TEXT;",
+        )
+        .context("adding media info columns")?;
+        // Parsed from the paths in series folders, same meaning as in the Film table
+        tx.execute_batch(
+            "ALTER TABLE Tape ADD COLUMN SERIE_NAME TEXT; \
+             ALTER TABLE Tape ADD COLUMN SEASON INTEGER; \
+             ALTER TABLE Tape ADD COLUMN EPISODE_NR INTEGER; \
+             ALTER TABLE Tape ADD COLUMN EPISODE_NAME TEXT;",
+        )
+        .context("adding episode columns")?;
+
+        tx.execute(
+            "CREATE TABLE Slice (NAME TEXT PRIMARY KEY, SCAN_DATE TEXT, SCANNER TEXT, \
+               ROW_COUNT INTEGER, STALE_SINCE INTEGER)",
+            [],
+        )
+        .context("creating Slice table")?;
+        let mut slice_stmt = tx.prepare(
+            "INSERT INTO Slice (NAME, SCAN_DATE, SCANNER, ROW_COUNT, STALE_SINCE) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+
         let mut stmt = tx.prepare(
             "INSERT INTO Tape \
-               (CODE_TAPE, TITLE, LOCATION, SHELF, ROW, POSITION, PATH, TYPE, DATE_PURCHASE, DURATION) \
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+               (CODE_TAPE, TITLE, LOCATION, SHELF, ROW, POSITION, PATH, TYPE, DATE_PURCHASE, DURATION, \
+                SOURCE, WIDTH, HEIGHT, LANGUAGES, SIZE, FINGERPRINT, \
+                SERIE_NAME, SEASON, EPISODE_NR, EPISODE_NAME) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, \
+                ?17, ?18, ?19, ?20)",
         )?;
 
-        for jsonl_path in jsonl_paths {
+        for slice in slices {
+            let jsonl_path = &slice.path;
             if !jsonl_path.exists() {
                 log::warn!("JSONL file missing, skipping: {}", jsonl_path.display());
                 continue;
             }
-            let f = File::open(jsonl_path)
-                .with_context(|| format!("opening {}", jsonl_path.display()))?;
-            let reader = BufReader::new(f);
+            let (meta, rows) = read_slice(jsonl_path)?;
             let mut count_in_file: usize = 0;
-            for (line_no, line) in reader.lines().enumerate() {
-                let line = line.with_context(|| {
-                    format!("reading line {} of {}", line_no + 1, jsonl_path.display())
-                })?;
-                if line.trim().is_empty() {
-                    continue;
-                }
-                let row: TapeRow = serde_json::from_str(&line).with_context(|| {
-                    format!("parsing line {} of {}", line_no + 1, jsonl_path.display())
-                })?;
+            for row in rows {
+                let episode = parse_episode(&rules, &row.path, &row.title);
                 stmt.execute(rusqlite::params![
                     next_code,
                     &row.title,
@@ -88,15 +194,78 @@ pub fn merge(
                     row.type_,
                     &row.date_purchase,
                     row.duration,
+                    &slice.name,
+                    row.width,
+                    row.height,
+                    (!row.languages.is_empty()).then(|| row.languages.join(", ")),
+                    row.size.map(|size| size as i64),
+                    &row.fingerprint,
+                    episode.as_ref().map(|e| &e.serie_name),
+                    episode.as_ref().and_then(|e| e.season),
+                    episode.as_ref().and_then(|e| e.episode_nr),
+                    episode.as_ref().and_then(|e| e.name.as_ref()),
                 ])?;
                 next_code += 1;
                 count_in_file += 1;
             }
-            log::info!("Merged {} rows from {}", count_in_file, jsonl_path.display());
+            slice_stmt.execute(rusqlite::params![
+                &slice.name,
+                meta.as_ref().map(|m| &m.scan_date),
+                meta.as_ref().map(|m| &m.scanner),
+                count_in_file,
+                slice.stale_since,
+            ])?;
+            if slice.stale_since.is_some() {
+                log::info!(
+                    "Merged {} rows from stale copy {}",
+                    count_in_file,
+                    jsonl_path.display()
+                );
+            } else {
+                log::info!("Merged {} rows from {}", count_in_file, jsonl_path.display());
+            }
             inserted += count_in_file;
         }
     }
+    tx.execute("CREATE INDEX TapeFingerprint ON Tape (FINGERPRINT)", [])
+        .context("indexing fingerprints")?;
+    // Folded copies of the searched fields, see fold.rs
+    register_functions(&tx)?;
+    tx.execute_batch(
+        "ALTER TABLE Tape ADD COLUMN TITLE_FOLDED TEXT; \
+         ALTER TABLE Tape ADD COLUMN SERIE_NAME_FOLDED TEXT; \
+         ALTER TABLE Tape ADD COLUMN PATH_FOLDED TEXT; \
+         UPDATE Tape SET TITLE_FOLDED=fold(TITLE), SERIE_NAME_FOLDED=fold(SERIE_NAME), \
+           PATH_FOLDED=fold(PATH); \
+         ALTER TABLE Film ADD COLUMN SERIE_NAME_FOLDED TEXT; \
+         ALTER TABLE Film ADD COLUMN NAME_FOLDED TEXT; \
+         ALTER TABLE Film ADD COLUMN DIRECTOR_FOLDED TEXT; \
+         ALTER TABLE Film ADD COLUMN PRODUCER_FOLDED TEXT; \
+         ALTER TABLE Film ADD COLUMN COMPOSER_FOLDED TEXT; \
+         UPDATE Film SET SERIE_NAME_FOLDED=fold(SERIE_NAME), NAME_FOLDED=fold(NAME), \
+           DIRECTOR_FOLDED=fold(DIRECTOR), PRODUCER_FOLDED=fold(PRODUCER), \
+           COMPOSER_FOLDED=fold(COMPOSER); \
+         ALTER TABLE Actor ADD COLUMN ACTOR_FOLDED TEXT; \
+         UPDATE Actor SET ACTOR_FOLDED=fold(ACTOR);",
+    )
+    .context("folding searched fields")?;
+    // Titles and person names, for the typo-tolerant search (see fuzzy.rs)
+    tx.execute_batch(
+        "CREATE TABLE SearchTerm (TERM TEXT PRIMARY KEY) WITHOUT ROWID; \
+         INSERT INTO SearchTerm \
+           SELECT TITLE_FOLDED FROM Tape WHERE TITLE_FOLDED != '' \
+           UNION SELECT SERIE_NAME_FOLDED FROM Film WHERE SERIE_NAME_FOLDED != '' \
+           UNION SELECT NAME_FOLDED FROM Film WHERE NAME_FOLDED != '' \
+           UNION SELECT DIRECTOR_FOLDED FROM Film WHERE DIRECTOR_FOLDED != '' \
+           UNION SELECT PRODUCER_FOLDED FROM Film WHERE PRODUCER_FOLDED != '' \
+           UNION SELECT COMPOSER_FOLDED FROM Film WHERE COMPOSER_FOLDED != '' \
+           UNION SELECT ACTOR_FOLDED FROM Actor WHERE ACTOR_FOLDED != '';",
+    )
+    .context("collecting search terms")?;
+    tx.pragma_update(None, "user_version", MERGED_SCHEMA_VERSION)?;
     tx.commit().context("committing transaction")?;
+    conn.close().map_err(|(_, e)| e).context("closing merged DB")?;
+    fs::rename(&tmp_db, merged_db).context("replacing merged DB")?;
 
     log::info!("Merge complete: {} HDD rows inserted into {}", inserted, merged_db.display());
     Ok(())
@@ -0,0 +1,638 @@
+//! Read the duration, resolution and audio languages of a video file from
+//! its container headers: MKV/WebM (EBML), MP4/MOV (ISO base media) and AVI
+//! (RIFF). Only the few header elements we need are read, the rest of the
+//! file is skipped over, so this is fast even on a slow USB disk.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Upper bound for the header structures we load in memory (MKV Info/Tracks,
+/// MP4 moov, AVI hdrl), so a corrupt size can't make us allocate gigabytes.
+const MAX_HEADER_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+pub struct MediaInfo {
+    pub duration_secs: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Audio track languages, ISO 639-2 codes ("fre", "eng"...), in track order.
+    pub languages: Vec<String>,
+}
+
+impl MediaInfo {
+    /// Duration rounded to minutes, the unit of Tape.DURATION.
+    pub fn duration_minutes(&self) -> Option<i32> {
+        self.duration_secs.filter(|secs| *secs > 0.0).map(|secs| (secs / 60.0).round() as i32)
+    }
+
+    fn add_language(&mut self, language: &str) {
+        let language = language.trim_end_matches('\0').trim();
+        if !language.is_empty()
+            && language != "und"
+            && !self.languages.iter().any(|l| l == language)
+        {
+            self.languages.push(language.to_string());
+        }
+    }
+}
+
+/// Probe the file at `path`. Returns None if the container isn't one we
+/// know or can't be parsed, in which case the caller falls back to whatever
+/// it could get from the file name.
+pub fn probe(path: &Path) -> Option<MediaInfo> {
+    let mut file = BufReader::new(File::open(path).ok()?);
+    let mut magic = [0u8; 12];
+    file.read_exact(&mut magic).ok()?;
+    file.seek(SeekFrom::Start(0)).ok()?;
+    let result = if magic[..4] == [0x1A, 0x45, 0xDF, 0xA3] {
+        probe_mkv(&mut file)
+    } else if &magic[..4] == b"RIFF" && &magic[8..12] == b"AVI " {
+        probe_avi(&mut file)
+    } else if matches!(&magic[4..8], b"ftyp" | b"moov" | b"mdat" | b"free" | b"wide" | b"skip") {
+        probe_mp4(&mut file)
+    } else {
+        None
+    };
+    if result.is_none() {
+        log::debug!("Could not probe {}", path.display());
+    }
+    result
+}
+
+fn read_payload<R: Read>(reader: &mut R, size: u64) -> Option<Vec<u8>> {
+    if size > MAX_HEADER_SIZE {
+        return None;
+    }
+    let mut data = vec![0u8; size as usize];
+    reader.read_exact(&mut data).ok()?;
+    Some(data)
+}
+
+fn be_uint(data: &[u8]) -> u64 {
+    data.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64)
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn le_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+// ---- MKV / WebM ----
+
+const EBML_SEGMENT: u64 = 0x18538067;
+const EBML_INFO: u64 = 0x1549A966;
+const EBML_TIMECODE_SCALE: u64 = 0x2AD7B1;
+const EBML_DURATION: u64 = 0x4489;
+const EBML_TRACKS: u64 = 0x1654AE6B;
+const EBML_TRACK_ENTRY: u64 = 0xAE;
+const EBML_TRACK_TYPE: u64 = 0x83;
+const EBML_LANGUAGE: u64 = 0x22B59C;
+const EBML_VIDEO: u64 = 0xE0;
+const EBML_PIXEL_WIDTH: u64 = 0xB0;
+const EBML_PIXEL_HEIGHT: u64 = 0xBA;
+const EBML_CLUSTER: u64 = 0x1F43B675;
+const MKV_TRACK_VIDEO: u64 = 1;
+const MKV_TRACK_AUDIO: u64 = 2;
+
+/// Variable-length integer: the number of leading zero bits gives the length.
+/// Element IDs keep their marker bit, sizes don't. None for an unknown size.
+fn read_vint<R: Read>(reader: &mut R, keep_marker: bool) -> Option<Option<u64>> {
+    let mut first = [0u8; 1];
+    reader.read_exact(&mut first).ok()?;
+    let length = first[0].leading_zeros() as usize + 1;
+    if length > 8 {
+        return None;
+    }
+    let mut rest = [0u8; 7];
+    reader.read_exact(&mut rest[..length - 1]).ok()?;
+    let marker = 0x80u8 >> (length - 1);
+    let first_bits = if keep_marker { first[0] } else { first[0] & !marker };
+    let value = be_uint(&rest[..length - 1]) | ((first_bits as u64) << (8 * (length - 1)));
+    let all_ones = (1u64 << (7 * length)) - 1;
+    Some(if !keep_marker && value == all_ones { None } else { Some(value) })
+}
+
+/// Returns (id, size); size is None for an element of unknown size.
+fn read_element_header<R: Read>(reader: &mut R) -> Option<(u64, Option<u64>)> {
+    let id = read_vint(reader, true)??;
+    let size = read_vint(reader, false)?;
+    Some((id, size))
+}
+
+/// Iterate over the elements contained in an in-memory EBML master element.
+fn ebml_children(mut data: &[u8]) -> impl Iterator<Item = (u64, &[u8])> {
+    std::iter::from_fn(move || {
+        let (id, size) = read_element_header(&mut data)?;
+        let size = usize::try_from(size?).ok()?;
+        let payload = data.get(..size)?;
+        data = &data[size..];
+        Some((id, payload))
+    })
+}
+
+fn ebml_float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes(data.try_into().ok()?) as f64),
+        8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+fn probe_mkv<R: Read + Seek>(file: &mut R) -> Option<MediaInfo> {
+    // EBML header
+    let (_, size) = read_element_header(file)?;
+    file.seek(SeekFrom::Current(size? as i64)).ok()?;
+
+    let (id, segment_size) = read_element_header(file)?;
+    if id != EBML_SEGMENT {
+        return None;
+    }
+    let segment_end = segment_size.map(|size| file.stream_position().unwrap_or(0) + size);
+
+    let mut info = MediaInfo::default();
+    let (mut found_info, mut found_tracks) = (false, false);
+    while !(found_info && found_tracks) {
+        if segment_end.is_some_and(|end| file.stream_position().unwrap_or(end) >= end) {
+            break;
+        }
+        let Some((id, size)) = read_element_header(file) else {
+            break;
+        };
+        // Info and Tracks come before the first Cluster in practice,
+        // no need to go through the whole file.
+        if id == EBML_CLUSTER {
+            break;
+        }
+        let size = size?;
+        match id {
+            EBML_INFO => {
+                let data = read_payload(file, size)?;
+                let mut scale = 1_000_000u64;
+                let mut duration = None;
+                for (child, payload) in ebml_children(&data) {
+                    match child {
+                        EBML_TIMECODE_SCALE => scale = be_uint(payload),
+                        EBML_DURATION => duration = ebml_float(payload),
+                        _ => {}
+                    }
+                }
+                info.duration_secs = duration.map(|d| d * scale as f64 / 1e9);
+                found_info = true;
+            }
+            EBML_TRACKS => {
+                let data = read_payload(file, size)?;
+                for (child, entry) in ebml_children(&data) {
+                    if child == EBML_TRACK_ENTRY {
+                        parse_mkv_track(entry, &mut info);
+                    }
+                }
+                found_tracks = true;
+            }
+            _ => {
+                file.seek(SeekFrom::Current(size as i64)).ok()?;
+            }
+        }
+    }
+    (found_info || found_tracks).then_some(info)
+}
+
+fn parse_mkv_track(entry: &[u8], info: &mut MediaInfo) {
+    let mut track_type = 0;
+    // The Matroska default when the element is absent
+    let mut language = "eng".to_string();
+    let mut size = (None, None);
+    for (id, payload) in ebml_children(entry) {
+        match id {
+            EBML_TRACK_TYPE => track_type = be_uint(payload),
+            EBML_LANGUAGE => language = String::from_utf8_lossy(payload).into_owned(),
+            EBML_VIDEO => {
+                for (video_id, value) in ebml_children(payload) {
+                    match video_id {
+                        EBML_PIXEL_WIDTH => size.0 = Some(be_uint(value) as u32),
+                        EBML_PIXEL_HEIGHT => size.1 = Some(be_uint(value) as u32),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    match track_type {
+        MKV_TRACK_VIDEO if info.width.is_none() => (info.width, info.height) = size,
+        MKV_TRACK_AUDIO => info.add_language(&language),
+        _ => {}
+    }
+}
+
+// ---- MP4 / MOV ----
+
+/// Iterate over the boxes contained in an in-memory box payload.
+fn mp4_children(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let size = be_u32(data, 0)? as usize;
+        let kind: [u8; 4] = data.get(4..8)?.try_into().ok()?;
+        let (header, size) = match size {
+            0 => (8, data.len()),
+            1 => (16, usize::try_from(be_uint(data.get(8..16)?)).ok()?),
+            _ => (8, size),
+        };
+        let payload = data.get(header..size)?;
+        data = &data[size..];
+        Some((kind, payload))
+    })
+}
+
+fn probe_mp4<R: Read + Seek>(file: &mut R) -> Option<MediaInfo> {
+    // Find the top-level moov box, skipping mdat etc. It's often at the end.
+    let moov = loop {
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header).ok()?;
+        let mut size = be_u32(&header, 0)? as u64;
+        let mut header_size = 8;
+        if size == 1 {
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large).ok()?;
+            size = u64::from_be_bytes(large);
+            header_size = 16;
+        }
+        if &header[4..8] == b"moov" {
+            break read_payload(file, size.checked_sub(header_size)?)?;
+        }
+        if size == 0 {
+            // Box extending to the end of the file, and it's not moov.
+            return None;
+        }
+        file.seek(SeekFrom::Current(size.checked_sub(header_size)? as i64)).ok()?;
+    };
+
+    let mut info = MediaInfo::default();
+    for (kind, payload) in mp4_children(&moov) {
+        match &kind {
+            b"mvhd" => {
+                let (timescale, duration) = if payload.first() == Some(&1) {
+                    (be_u32(payload, 20)?, be_uint(payload.get(24..32)?))
+                } else {
+                    (be_u32(payload, 12)?, be_u32(payload, 16)? as u64)
+                };
+                if timescale > 0 {
+                    info.duration_secs = Some(duration as f64 / timescale as f64);
+                }
+            }
+            b"trak" => parse_mp4_track(payload, &mut info),
+            _ => {}
+        }
+    }
+    Some(info)
+}
+
+fn parse_mp4_track(trak: &[u8], info: &mut MediaInfo) {
+    let mut size = (None, None);
+    let mut handler = [0u8; 4];
+    let mut language = None;
+    for (kind, payload) in mp4_children(trak) {
+        match &kind {
+            b"tkhd" => {
+                // 16.16 fixed point, at the end of the box
+                let offset = if payload.first() == Some(&1) { 88 } else { 76 };
+                size = (
+                    be_u32(payload, offset).map(|w| w >> 16),
+                    be_u32(payload, offset + 4).map(|h| h >> 16),
+                );
+            }
+            b"mdia" => {
+                for (mdia_kind, mdia_payload) in mp4_children(payload) {
+                    match &mdia_kind {
+                        b"mdhd" => {
+                            let offset = if mdia_payload.first() == Some(&1) { 32 } else { 20 };
+                            language = mdia_payload.get(offset..offset + 2).map(|packed| {
+                                mp4_language(u16::from_be_bytes([packed[0], packed[1]]))
+                            });
+                        }
+                        b"hdlr" => {
+                            if let Some(kind) = mdia_payload.get(8..12) {
+                                handler.copy_from_slice(kind);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    match &handler {
+        b"vide" if info.width.is_none() && size.0.is_some_and(|w| w > 0) => {
+            (info.width, info.height) = size
+        }
+        b"soun" => {
+            if let Some(language) = language {
+                info.add_language(&language);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// ISO 639-2 code packed as three 5-bit letters (offset by 0x60).
+fn mp4_language(packed: u16) -> String {
+    [10u16, 5, 0]
+        .iter()
+        .map(|shift| char::from((((packed >> shift) & 0x1F) as u8).wrapping_add(0x60)))
+        .collect()
+}
+
+// ---- AVI ----
+
+fn probe_avi<R: Read + Seek>(file: &mut R) -> Option<MediaInfo> {
+    // "RIFF" size "AVI ", then the "hdrl" LIST comes first.
+    file.seek(SeekFrom::Start(12)).ok()?;
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header).ok()?;
+    if &header[..4] != b"LIST" || &header[8..12] != b"hdrl" {
+        return None;
+    }
+    let hdrl = read_payload(file, le_u32(&header, 4)?.checked_sub(4)? as u64)?;
+
+    let mut info = MediaInfo::default();
+    let mut total_frames = None;
+    let mut usec_per_frame = 0;
+    for (kind, payload) in riff_children(&hdrl) {
+        match &kind {
+            b"avih" => {
+                usec_per_frame = le_u32(payload, 0)?;
+                total_frames = total_frames.or(le_u32(payload, 16));
+                info.width = le_u32(payload, 32).filter(|w| *w > 0);
+                info.height = le_u32(payload, 36).filter(|h| *h > 0);
+            }
+            // OpenDML (files over 1GB): avih only counts the frames of the first RIFF chunk
+            b"LIST" if payload.get(..4) == Some(b"odml") => {
+                for (odml_kind, odml_payload) in riff_children(&payload[4..]) {
+                    if &odml_kind == b"dmlh" {
+                        total_frames = le_u32(odml_payload, 0);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(frames) = total_frames.filter(|_| usec_per_frame > 0) {
+        info.duration_secs = Some(frames as f64 * usec_per_frame as f64 / 1e6);
+    }
+    Some(info)
+}
+
+/// Iterate over the chunks of an in-memory RIFF list (chunks are padded to even sizes).
+fn riff_children(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let kind: [u8; 4] = data.get(..4)?.try_into().ok()?;
+        let size = le_u32(data, 4)? as usize;
+        // Checked, a size near u32::MAX would overflow usize on 32-bit targets
+        let end = 8usize.checked_add(size)?;
+        let payload = data.get(8..end)?;
+        data = end.checked_add(size % 2).and_then(|next| data.get(next..)).unwrap_or_default();
+        Some((kind, payload))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// An EBML element, with its size on 8 bytes
+    fn ebml(id: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut element = id.to_vec();
+        element.push(0x01);
+        element.extend_from_slice(&(payload.len() as u64).to_be_bytes()[1..]);
+        element.extend_from_slice(payload);
+        element
+    }
+
+    fn mkv(segment: &[u8]) -> Vec<u8> {
+        let mut file = ebml(&[0x1A, 0x45, 0xDF, 0xA3], &[0x42, 0x82, 0x84, b'w', b'e', b'b', b'm']);
+        file.extend(ebml(&[0x18, 0x53, 0x80, 0x67], segment));
+        file
+    }
+
+    fn mkv_segment() -> Vec<u8> {
+        let mut info = ebml(&[0x2A, 0xD7, 0xB1], &[0x0F, 0x42, 0x40]); // 1ms
+        info.extend(ebml(&[0x44, 0x89], &5_400_000f64.to_be_bytes()));
+        let mut video = ebml(&[0xB0], &[0x07, 0x80]);
+        video.extend(ebml(&[0xBA], &[0x04, 0x38]));
+        let mut video_track = ebml(&[0x83], &[1]);
+        video_track.extend(ebml(&[0xE0], &video));
+        let mut french_track = ebml(&[0x83], &[2]);
+        french_track.extend(ebml(&[0x22, 0xB5, 0x9C], b"fre"));
+        let default_track = ebml(&[0x83], &[2]); // no language: English
+        let mut tracks = ebml(&[0xAE], &video_track);
+        tracks.extend(ebml(&[0xAE], &french_track));
+        tracks.extend(ebml(&[0xAE], &default_track));
+
+        let mut segment = ebml(&[0x11, 0x4D, 0x9B, 0x74], &[0; 10]); // SeekHead, skipped
+        segment.extend(ebml(&[0x15, 0x49, 0xA9, 0x66], &info));
+        segment.extend(ebml(&[0x16, 0x54, 0xAE, 0x6B], &tracks));
+        segment
+    }
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut mp4_box = (payload.len() as u32 + 8).to_be_bytes().to_vec();
+        mp4_box.extend_from_slice(kind);
+        mp4_box.extend_from_slice(payload);
+        mp4_box
+    }
+
+    fn mp4_track(handler: &[u8; 4], size: (u32, u32), language: &str) -> Vec<u8> {
+        let mut tkhd = vec![0; 84];
+        tkhd[76..80].copy_from_slice(&(size.0 << 16).to_be_bytes());
+        tkhd[80..84].copy_from_slice(&(size.1 << 16).to_be_bytes());
+        let mut hdlr = vec![0; 24];
+        hdlr[8..12].copy_from_slice(handler);
+        let packed = language.bytes().fold(0u16, |packed, c| (packed << 5) | (c - 0x60) as u16);
+        let mut mdhd = vec![0; 24];
+        mdhd[20..22].copy_from_slice(&packed.to_be_bytes());
+        let mut mdia = mp4_box(b"mdhd", &mdhd);
+        mdia.extend(mp4_box(b"hdlr", &hdlr));
+        let mut trak = mp4_box(b"tkhd", &tkhd);
+        trak.extend(mp4_box(b"mdia", &mdia));
+        mp4_box(b"trak", &trak)
+    }
+
+    fn mp4_moov() -> Vec<u8> {
+        let mut mvhd = vec![0; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&3_600_000u32.to_be_bytes());
+        let mut moov = mp4_box(b"mvhd", &mvhd);
+        moov.extend(mp4_track(b"vide", (1280, 720), "und"));
+        moov.extend(mp4_track(b"soun", (0, 0), "fre"));
+        moov.extend(mp4_track(b"soun", (0, 0), "eng"));
+        moov
+    }
+
+    fn mp4(moov: &[u8]) -> Vec<u8> {
+        let mut file = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        // mdat with a 64-bit size
+        file.extend_from_slice(&[0, 0, 0, 1]);
+        file.extend_from_slice(b"mdat");
+        file.extend_from_slice(&20u64.to_be_bytes());
+        file.extend_from_slice(&[0xAB; 4]);
+        file.extend(mp4_box(b"moov", moov));
+        file
+    }
+
+    fn riff_chunk(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(payload);
+        if payload.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn avi(odml_frames: Option<u32>) -> Vec<u8> {
+        let mut avih = vec![0; 56];
+        avih[0..4].copy_from_slice(&40_000u32.to_le_bytes()); // 25 fps
+        avih[16..20].copy_from_slice(&45_000u32.to_le_bytes()); // 30 minutes
+        avih[32..36].copy_from_slice(&720u32.to_le_bytes());
+        avih[36..40].copy_from_slice(&576u32.to_le_bytes());
+        let mut hdrl = b"hdrl".to_vec();
+        hdrl.extend(riff_chunk(b"avih", &avih));
+        hdrl.extend(riff_chunk(b"JUNK", &[0; 3])); // odd size, padded
+        if let Some(frames) = odml_frames {
+            let mut odml = b"odml".to_vec();
+            odml.extend(riff_chunk(b"dmlh", &frames.to_le_bytes()));
+            hdrl.extend(riff_chunk(b"LIST", &odml));
+        }
+        let mut riff = b"AVI ".to_vec();
+        riff.extend(riff_chunk(b"LIST", &hdrl));
+        riff_chunk(b"RIFF", &riff)
+    }
+
+    #[test]
+    fn mkv_header() {
+        let info = probe_mkv(&mut Cursor::new(mkv(&mkv_segment()))).unwrap();
+        assert_eq!(info.duration_minutes(), Some(90));
+        assert_eq!((info.width, info.height), (Some(1920), Some(1080)));
+        assert_eq!(info.languages, ["fre", "eng"]);
+    }
+
+    #[test]
+    fn mkv_segment_of_unknown_size() {
+        let mut file = mkv(&[]);
+        let header_len = file.len() - 8;
+        file.truncate(header_len);
+        file.extend_from_slice(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        file.extend(mkv_segment());
+        let info = probe_mkv(&mut Cursor::new(file)).unwrap();
+        assert_eq!(info.duration_minutes(), Some(90));
+    }
+
+    #[test]
+    fn mkv_truncated_or_oversized() {
+        let file = mkv(&mkv_segment());
+        for len in [0, 3, 20, file.len() - 1] {
+            assert!(probe_mkv(&mut Cursor::new(&file[..len])).is_none(), "truncated to {}", len);
+        }
+        // Cut in the header of Tracks: what was read before is kept
+        let tracks = file.windows(4).position(|id| id == [0x16, 0x54, 0xAE, 0x6B]).unwrap();
+        let info = probe_mkv(&mut Cursor::new(&file[..tracks + 2])).unwrap();
+        assert_eq!(info.duration_minutes(), Some(90));
+        assert!(info.languages.is_empty());
+        let mut huge_info = ebml(&[0x15, 0x49, 0xA9, 0x66], &[]);
+        huge_info.truncate(huge_info.len() - 7);
+        huge_info.extend_from_slice(&[0x7F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert!(probe_mkv(&mut Cursor::new(mkv(&huge_info))).is_none());
+    }
+
+    #[test]
+    fn mp4_header() {
+        let info = probe_mp4(&mut Cursor::new(mp4(&mp4_moov()))).unwrap();
+        assert_eq!(info.duration_minutes(), Some(60));
+        assert_eq!((info.width, info.height), (Some(1280), Some(720)));
+        assert_eq!(info.languages, ["fre", "eng"]);
+    }
+
+    #[test]
+    fn mp4_truncated_or_oversized() {
+        let file = mp4(&mp4_moov());
+        for len in [0, 7, 30, file.len() - 1] {
+            assert!(probe_mp4(&mut Cursor::new(&file[..len])).is_none(), "truncated to {}", len);
+        }
+        // A box smaller than its header, or a moov too big to load
+        let mut tiny = mp4_box(b"ftyp", &[]);
+        tiny[..4].copy_from_slice(&4u32.to_be_bytes());
+        assert!(probe_mp4(&mut Cursor::new(tiny)).is_none());
+        let mut huge = mp4_box(b"moov", &[0; 8]);
+        huge[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(probe_mp4(&mut Cursor::new(huge)).is_none());
+        let mut huge_large = vec![0, 0, 0, 1];
+        huge_large.extend_from_slice(b"moov");
+        huge_large.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert!(probe_mp4(&mut Cursor::new(huge_large)).is_none());
+
+        // A child box claiming more than its parent has: the boxes before it are still read
+        let mut moov = mp4_moov();
+        let trak = moov.windows(4).position(|kind| kind == b"trak").unwrap() - 4;
+        moov[trak..trak + 4].copy_from_slice(&10_000u32.to_be_bytes());
+        let info = probe_mp4(&mut Cursor::new(mp4(&moov))).unwrap();
+        assert_eq!(info.duration_minutes(), Some(60));
+        assert_eq!(info.width, None);
+    }
+
+    #[test]
+    fn avi_header() {
+        let info = probe_avi(&mut Cursor::new(avi(None))).unwrap();
+        assert_eq!(info.duration_minutes(), Some(30));
+        assert_eq!((info.width, info.height), (Some(720), Some(576)));
+        let info = probe_avi(&mut Cursor::new(avi(Some(90_000)))).unwrap();
+        assert_eq!(info.duration_minutes(), Some(60));
+    }
+
+    #[test]
+    fn avi_truncated_or_oversized() {
+        let file = avi(None);
+        for len in [0, 12, 30, file.len() - 1] {
+            assert!(probe_avi(&mut Cursor::new(&file[..len])).is_none(), "truncated to {}", len);
+        }
+        let mut small_hdrl = file.clone();
+        small_hdrl[16..20].copy_from_slice(&2u32.to_le_bytes());
+        assert!(probe_avi(&mut Cursor::new(small_hdrl)).is_none());
+        // A chunk claiming more than the list has is ignored
+        let mut huge_avih = file;
+        huge_avih[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+        let info = probe_avi(&mut Cursor::new(huge_avih)).unwrap();
+        assert_eq!(info.duration_secs, None);
+    }
+
+    #[test]
+    fn riff_chunk_sizes_near_the_limit() {
+        for size in [u32::MAX, u32::MAX - 1] {
+            let mut data = riff_chunk(b"JUNK", &[0; 2]);
+            data[4..8].copy_from_slice(&size.to_le_bytes());
+            assert_eq!(riff_children(&data).count(), 0, "size {}", size);
+        }
+        let data = [riff_chunk(b"JUNK", &[0; 3]), riff_chunk(b"avih", &[1; 4])].concat();
+        let kinds: Vec<_> = riff_children(&data).map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [*b"JUNK", *b"avih"]);
+    }
+
+    #[test]
+    fn probe_by_magic() {
+        let dir = tempfile::tempdir().unwrap();
+        let files =
+            [("a.mkv", mkv(&mkv_segment())), ("b.mp4", mp4(&mp4_moov())), ("c.avi", avi(None))];
+        for (name, content) in &files {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            assert!(probe(&path).is_some(), "{}", name);
+        }
+        let other = dir.path().join("d.mpg");
+        std::fs::write(&other, [0, 0, 1, 0xBA, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert!(probe(&other).is_none());
+        assert!(probe(&dir.path().join("missing.mkv")).is_none());
+    }
+}
@@ -0,0 +1,670 @@
+//! The search query language, e.g. `actor:gabin year:1950..1960 type:dvd loc:ELORA_2 -serie`.
+//!
+//! A query is a list of terms, which must all match. A term is either a word,
+//! searched in every field, or `qualifier:value`, searched in one field:
+//! - `title:`, `serie:`, `actor:`, `director:`, `producer:`, `composer:` (text)
+//! - `path:` (path of the HDD files, not searched by the other terms)
+//! - `year:1955`, `year:1950..1960`, `year:..1960`, `year:1950..` (film year)
+//! - `type:tape|dvd|bluray|file` (type of support)
+//! - `loc:ELORA_2` (location of the support, the whole name)
+//!
+//! `"quoted phrases"` keep their spaces (`""` in them is a quote), and `-term` excludes
+//! what matches the term.
+//! A text value starting with `=` must match the whole field, e.g. `actor:="Jean Gabin"`.
+
+use crate::enums::SupportType;
+use crate::fold::fold;
+use crate::user_data::{ON_LOAN_SQL, SUPPORT_KEY_SQL};
+use rusqlite::types::Value;
+use std::fmt;
+
+#[derive(Debug)]
+pub struct QueryError(String);
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextField {
+    Any,
+    Title,
+    Serie,
+    Actor,
+    Director,
+    Producer,
+    Composer,
+    Path,
+}
+
+impl TextField {
+    /// The qualifier for this field in queries
+    pub fn name(self) -> &'static str {
+        match self {
+            TextField::Any => "any",
+            TextField::Title => "title",
+            TextField::Serie => "serie",
+            TextField::Actor => "actor",
+            TextField::Director => "director",
+            TextField::Producer => "producer",
+            TextField::Composer => "composer",
+            TextField::Path => "path",
+        }
+    }
+}
+
+pub enum Condition {
+    /// Folded text, see fold.rs
+    Text(TextField, String),
+    /// Folded text, equal to the whole field
+    Exact(TextField, String),
+    Year(Option<i32>, Option<i32>),
+    Type(SupportType),
+    Location(String),
+    /// At least one of these, for the typo-tolerant search
+    AnyOf(Vec<Condition>),
+}
+
+pub struct Term {
+    pub negated: bool,
+    pub condition: Condition,
+}
+
+pub struct SearchQuery {
+    pub terms: Vec<Term>,
+}
+
+fn error(message: String) -> Result<SearchQuery, QueryError> {
+    Err(QueryError(message))
+}
+
+/// Split `text` on whitespace, except inside double quotes.
+fn tokenize(text: &str) -> Result<Vec<String>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_quotes = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                token.push(c);
+            }
+            _ if c.is_whitespace() && !in_quotes => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            _ => token.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(QueryError("Missing closing quote".to_string()));
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// `token` without its quotes, `""` inside quotes standing for a quote,
+/// e.g. `"Le ""Grand"" Bleu"`.
+fn unquote(token: &str) -> String {
+    let mut text = String::with_capacity(token.len());
+    let mut in_quotes = false;
+    let mut chars = token.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                text.push(c);
+            }
+            '"' => in_quotes = !in_quotes,
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+fn parse_year(year: &str, value: &str) -> Result<Option<i32>, QueryError> {
+    if year.is_empty() {
+        return Ok(None);
+    }
+    year.parse().map(Some).map_err(|_| {
+        QueryError(format!("Invalid year range {:?}, expected e.g. 1950..1960", value))
+    })
+}
+
+fn parse_support_type(value: &str) -> Option<SupportType> {
+    match value.to_lowercase().as_str() {
+        "tape" | "vhs" | "cassette" | "k7" => Some(SupportType::Tape),
+        "dvd" => Some(SupportType::Dvd),
+        "bluray" | "blu-ray" | "br" => Some(SupportType::Bluray),
+        "file" | "hdd" | "computer" => Some(SupportType::ComputerFile),
+        _ => None,
+    }
+}
+
+impl SearchQuery {
+    pub fn parse(text: &str) -> Result<SearchQuery, QueryError> {
+        let mut terms: Vec<Term> = Vec::new();
+        for token in tokenize(text)? {
+            let (negated, token) = match token.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => (false, token.as_str()),
+            };
+            // A lone `-` (e.g. while typing `-term`) or `""` has nothing to search for
+            if unquote(token).is_empty() {
+                continue;
+            }
+            let qualifier = token
+                .split_once(':')
+                .map(|(name, value)| (name.to_lowercase(), unquote(value)))
+                .filter(|(name, _)| is_qualifier(name));
+            let Some((name, value)) = qualifier else {
+                // Not a qualifier, e.g. "Shadoks:". Each word is a separate
+                // term, which can match a different field than the other words.
+                let text = fold(&unquote(token));
+                terms.push(Term { negated, condition: Condition::Text(TextField::Any, text) });
+                continue;
+            };
+            if value.is_empty() || value == "=" {
+                return error(format!("Missing value after {}:", name));
+            }
+            let text = |field| match value.strip_prefix('=') {
+                Some(exact) => Condition::Exact(field, fold(exact)),
+                None => Condition::Text(field, fold(&value)),
+            };
+            let condition = match name.as_str() {
+                "title" => text(TextField::Title),
+                "serie" | "series" => text(TextField::Serie),
+                "actor" => text(TextField::Actor),
+                "director" => text(TextField::Director),
+                "producer" => text(TextField::Producer),
+                "composer" => text(TextField::Composer),
+                "path" => text(TextField::Path),
+                "year" => match value.split_once("..") {
+                    Some((from, to)) => {
+                        Condition::Year(parse_year(from, &value)?, parse_year(to, &value)?)
+                    }
+                    None => {
+                        let year = parse_year(&value, &value)?;
+                        Condition::Year(year, year)
+                    }
+                },
+                "type" => match parse_support_type(&value) {
+                    Some(support_type) => Condition::Type(support_type),
+                    None => {
+                        return error(format!(
+                            "Unknown type {:?}, expected tape, dvd, bluray or file",
+                            value
+                        ));
+                    }
+                },
+                _ => Condition::Location(value),
+            };
+            terms.push(Term { negated, condition });
+        }
+        if terms.is_empty() {
+            return error("Empty query".to_string());
+        }
+        Ok(SearchQuery { terms })
+    }
+
+    /// The text of a query made only of unqualified words, for the
+    /// typo-tolerant search.
+    pub fn plain_text(&self) -> Option<String> {
+        let words: Option<Vec<&str>> = self
+            .terms
+            .iter()
+            .map(|term| match term {
+                Term { negated: false, condition: Condition::Text(TextField::Any, text) } => {
+                    Some(text.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        words.map(|words| words.join(" "))
+    }
+
+    /// The (folded) texts searched for, to show where they were found.
+    pub fn text_terms(&self) -> Vec<(TextField, String)> {
+        fn collect(condition: &Condition, terms: &mut Vec<(TextField, String)>) {
+            match condition {
+                Condition::Text(field, text) | Condition::Exact(field, text) => {
+                    terms.push((*field, text.clone()))
+                }
+                Condition::AnyOf(conditions) => {
+                    conditions.iter().for_each(|condition| collect(condition, terms))
+                }
+                _ => {}
+            }
+        }
+        let mut terms = Vec::new();
+        for term in self.terms.iter().filter(|term| !term.negated) {
+            collect(&term.condition, &mut terms);
+        }
+        terms
+    }
+
+    /// A query searching for any of the (folded) `texts` in every field.
+    pub fn from_alternatives(texts: &[String]) -> SearchQuery {
+        let conditions =
+            texts.iter().map(|text| Condition::Text(TextField::Any, text.clone())).collect();
+        SearchQuery {
+            terms: vec![Term { negated: false, condition: Condition::AnyOf(conditions) }],
+        }
+    }
+
+    /// The SQL condition on the Tape and Film tables, with its parameters.
+    pub fn to_sql(&self, filter: &SearchFilter) -> (String, Vec<Value>) {
+        let mut params: Vec<Value> = Vec::new();
+        let mut conditions: Vec<String> = self
+            .terms
+            .iter()
+            .map(|term| {
+                let sql = term.condition.to_sql(&mut params);
+                // A NULL column makes the condition NULL, which NOT doesn't turn into true
+                if term.negated { format!("NOT IFNULL({}, 0)", sql) } else { sql }
+            })
+            .collect();
+        conditions.extend(filter.to_sql(&mut params));
+        (conditions.join(" AND "), params)
+    }
+}
+
+/// Order of the search results
+#[derive(Clone, Copy, Default)]
+pub enum SortOrder {
+    /// By displayed name, ignoring leading articles, then by episode
+    #[default]
+    Title,
+    Year,
+    Duration,
+    /// Where the support is stored: location, shelf, row, position (or path)
+    Location,
+    RecentlyAdded,
+}
+
+impl SortOrder {
+    /// The ORDER BY clause, using the SORT_NAME, SEASON and EPISODE
+    /// columns of the search statement. It ends with the support and film codes,
+    /// which make each row unique, so that the pages of results (see
+    /// SearchResults::fetch) neither overlap nor skip the rows that tie.
+    pub fn to_sql(self, group_by_support: bool) -> String {
+        let (order, direction) = match self {
+            SortOrder::Title => (
+                "SORT_NAME, SEASON, EPISODE, title_sort_key(COALESCE(Film.NAME, Tape.EPISODE_NAME))",
+                "",
+            ),
+            SortOrder::Year => ("Film.YEAR IS NULL, Film.YEAR, SORT_NAME", ""),
+            SortOrder::Duration => (
+                "COALESCE(NULLIF(Film.DURATION, 0), Tape.DURATION) IS NULL, \
+                 COALESCE(NULLIF(Film.DURATION, 0), Tape.DURATION), SORT_NAME",
+                "",
+            ),
+            SortOrder::Location => {
+                ("Tape.LOCATION, Tape.SHELF, Tape.ROW, Tape.POSITION, Tape.PATH, SORT_NAME", "")
+            }
+            SortOrder::RecentlyAdded => ("Tape.DATE_PURCHASE DESC", " DESC"),
+        };
+        // When grouping, there's one row per support
+        if group_by_support {
+            format!("{}, Tape.CODE_TAPE{}", order, direction)
+        } else {
+            format!("{}, Tape.CODE_TAPE{}, Film.CODE{}", order, direction, direction)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Origin {
+    Recorded,
+    Bought,
+}
+
+/// Tape.ORIGIN is "Enregistré" or "Taped" for recordings, see enums::color_for_support
+const RECORDED: &str = "(Tape.ORIGIN LIKE 'E%' OR Tape.ORIGIN LIKE 'T%')";
+
+/// The filter panel of the UI, applied on top of the query.
+#[derive(Clone, Default)]
+pub struct SearchFilter {
+    /// Empty for all types
+    pub support_types: Vec<SupportType>,
+    pub location: Option<String>,
+    pub year: (Option<i32>, Option<i32>),
+    /// In minutes
+    pub duration: (Option<i32>, Option<i32>),
+    pub origin: Option<Origin>,
+    pub on_loan: Option<bool>,
+    /// The user data, see user_data.rs
+    pub favourite_only: bool,
+    pub watched: Option<bool>,
+    pub min_rating: Option<i32>,
+}
+
+impl SearchFilter {
+    fn to_sql(&self, params: &mut Vec<Value>) -> Vec<String> {
+        let mut param = |value: Value| {
+            params.push(value);
+            format!("?{}", params.len())
+        };
+        let mut conditions = Vec::new();
+        if !self.support_types.is_empty() {
+            let types: Vec<String> =
+                self.support_types.iter().map(|t| param((*t as i32).into())).collect();
+            conditions.push(format!("Tape.TYPE IN ({})", types.join(", ")));
+        }
+        if let Some(location) = &self.location {
+            conditions.push(format!("Tape.LOCATION = {}", param(location.clone().into())));
+        }
+        if self.year != (None, None) {
+            conditions.push(range_sql("Film.YEAR", self.year, &mut param));
+        }
+        if self.duration != (None, None) {
+            // HDD files have their duration on the Tape row
+            let duration = "COALESCE(NULLIF(Film.DURATION, 0), Tape.DURATION)";
+            conditions.push(range_sql(duration, self.duration, &mut param));
+        }
+        match self.origin {
+            Some(Origin::Recorded) => conditions.push(RECORDED.to_string()),
+            Some(Origin::Bought) => conditions.push(format!("NOT IFNULL({}, 1)", RECORDED)),
+            None => {}
+        }
+        match self.on_loan {
+            // Our loans override the ON_LOAN flag, see user_data.rs
+            Some(true) => conditions.push(format!("{} = 1", *ON_LOAN_SQL)),
+            Some(false) => conditions.push(format!("{} = 0", *ON_LOAN_SQL)),
+            None => {}
+        }
+        // The user data (U) of the film and support of the row
+        let user_data_join =
+            format!("U.FILM_CODE = IFNULL(Film.CODE, 0) AND U.SUPPORT_KEY = ({})", SUPPORT_KEY_SQL);
+        let mut user_data = Vec::new();
+        if self.favourite_only {
+            user_data.push("U.FAVOURITE".to_string());
+        }
+        if let Some(watched) = self.watched {
+            // No row at all means not watched
+            conditions.push(format!(
+                "{}EXISTS (SELECT 1 FROM userdb.UserData U WHERE {} AND U.WATCHED)",
+                if watched { "" } else { "NOT " },
+                user_data_join
+            ));
+        }
+        if let Some(rating) = self.min_rating {
+            user_data.push(format!("U.RATING >= {}", param(rating.into())));
+        }
+        if !user_data.is_empty() {
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM userdb.UserData U WHERE {} AND {})",
+                user_data_join,
+                user_data.join(" AND ")
+            ));
+        }
+        conditions
+    }
+}
+
+/// `column` between `from` and `to`, either of them being optional.
+fn range_sql(
+    column: &str,
+    (from, to): (Option<i32>, Option<i32>),
+    param: &mut impl FnMut(Value) -> String,
+) -> String {
+    match (from, to) {
+        (Some(from), Some(to)) => {
+            format!("{} BETWEEN {} AND {}", column, param(from.into()), param(to.into()))
+        }
+        (Some(from), None) => format!("{} >= {}", column, param(from.into())),
+        (None, Some(to)) => format!("{} <= {}", column, param(to.into())),
+        (None, None) => format!("{} > 0", column),
+    }
+}
+
+fn is_qualifier(name: &str) -> bool {
+    matches!(
+        name,
+        "title"
+            | "serie"
+            | "series"
+            | "actor"
+            | "director"
+            | "producer"
+            | "composer"
+            | "path"
+            | "year"
+            | "type"
+            | "loc"
+            | "location"
+    )
+}
+
+/// `%text%`, with the LIKE wildcards in `text` escaped (see ESCAPE below).
+pub fn like_pattern(text: &str) -> String {
+    let mut pattern = String::with_capacity(text.len() + 2);
+    pattern.push('%');
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// The condition on the folded columns of `field`, `matches` giving the one on a column.
+fn field_sql(field: TextField, matches: impl Fn(&str) -> String) -> String {
+    let actor =
+        format!("Film.CODE IN (SELECT CODE_FILM FROM Actor WHERE {})", matches("ACTOR_FOLDED"));
+    let columns: Vec<String> = match field {
+        TextField::Any => vec![
+            matches("Tape.TITLE_FOLDED"),
+            matches("Film.SERIE_NAME_FOLDED"),
+            matches("Film.NAME_FOLDED"),
+            matches("Film.DIRECTOR_FOLDED"),
+            matches("Film.PRODUCER_FOLDED"),
+            matches("Film.COMPOSER_FOLDED"),
+            actor,
+        ],
+        TextField::Title => vec![matches("Tape.TITLE_FOLDED"), matches("Film.NAME_FOLDED")],
+        TextField::Serie => {
+            vec![matches("Film.SERIE_NAME_FOLDED"), matches("Tape.SERIE_NAME_FOLDED")]
+        }
+        TextField::Actor => vec![actor],
+        TextField::Director => vec![matches("Film.DIRECTOR_FOLDED")],
+        TextField::Producer => vec![matches("Film.PRODUCER_FOLDED")],
+        TextField::Composer => vec![matches("Film.COMPOSER_FOLDED")],
+        TextField::Path => vec![matches("Tape.PATH_FOLDED")],
+    };
+    format!("({})", columns.join(" OR "))
+}
+
+impl Condition {
+    fn to_sql(&self, params: &mut Vec<Value>) -> String {
+        let mut param = |value: Value| {
+            params.push(value);
+            format!("?{}", params.len())
+        };
+        match self {
+            Condition::Text(field, text) => {
+                let p = param(Value::Text(like_pattern(text)));
+                field_sql(*field, |column| format!("{} LIKE {} ESCAPE '\\'", column, p))
+            }
+            Condition::Exact(field, text) => {
+                let p = param(Value::Text(text.clone()));
+                field_sql(*field, |column| format!("{} = {}", column, p))
+            }
+            Condition::Year(from, to) => range_sql("Film.YEAR", (*from, *to), &mut param),
+            Condition::Type(support_type) => {
+                format!("Tape.TYPE = {}", param((*support_type as i32).into()))
+            }
+            // The whole name, loc:ELORA_1 isn't ELORA_10. NOCASE ignores ASCII case,
+            // enough for location names.
+            Condition::Location(location) => {
+                format!("Tape.LOCATION = {} COLLATE NOCASE", param(Value::Text(location.clone())))
+            }
+            Condition::AnyOf(conditions) => {
+                let alternatives: Vec<String> =
+                    conditions.iter().map(|condition| condition.to_sql(params)).collect();
+                format!("({})", alternatives.join(" OR "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn sql(text: &str) -> (String, Vec<Value>) {
+        SearchQuery::parse(text).unwrap().to_sql(&SearchFilter::default())
+    }
+
+    fn parse_error(text: &str) -> String {
+        SearchQuery::parse(text).err().unwrap().to_string()
+    }
+
+    fn text(text: &str) -> Value {
+        Value::Text(text.to_string())
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse_error(r#"title:"le mepris"#), "Missing closing quote");
+        assert_eq!(parse_error("title:"), "Missing value after title:");
+        assert_eq!(parse_error("actor:="), "Missing value after actor:");
+        assert_eq!(
+            parse_error("year:19x0"),
+            r#"Invalid year range "19x0", expected e.g. 1950..1960"#
+        );
+        assert_eq!(
+            parse_error("year:1950..x"),
+            r#"Invalid year range "1950..x", expected e.g. 1950..1960"#
+        );
+        assert!(parse_error("type:laserdisc").starts_with(r#"Unknown type "laserdisc""#));
+        assert_eq!(parse_error(""), "Empty query");
+        assert_eq!(parse_error("  "), "Empty query");
+    }
+
+    #[test]
+    fn words_and_phrases() {
+        // Each word is a term of its own, folded
+        let (condition, params) = sql("Mépris  GODARD");
+        assert_eq!(params, [text("%mepris%"), text("%godard%")]);
+        assert_eq!(condition.matches("Film.NAME_FOLDED LIKE").count(), 2);
+        assert!(condition.contains(") AND ("));
+        let (_, params) = sql(r#""Le Mépris" godard"#);
+        assert_eq!(params, [text("%le mepris%"), text("%godard%")]);
+        // Not a qualifier, searched as a word
+        let (_, params) = sql("Shadoks:");
+        assert_eq!(params, [text("%shadoks:%")]);
+    }
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        let (condition, params) = sql(r"100%_\");
+        assert_eq!(params, [text(r"%100\%\_\\%")]);
+        assert!(condition.contains(r"LIKE ?1 ESCAPE '\'"));
+        // And SQLite sees them as plain characters
+        let conn = Connection::open_in_memory().unwrap();
+        let like = |value: &str| -> bool {
+            conn.query_row(r"SELECT ?1 LIKE ?2 ESCAPE '\'", (value, &params[0]), |row| row.get(0))
+                .unwrap()
+        };
+        assert!(like(r"le 100%_\ !"));
+        assert!(!like(r"le 1000x\ !"));
+    }
+
+    #[test]
+    fn quotes_in_quotes() {
+        let (_, params) = sql(r#"title:="Le ""Grand"" Bleu""#);
+        assert_eq!(params, [text(r#"le "grand" bleu"#)]);
+        let (_, params) = sql(r#""5"" 1/4" disquette"#);
+        assert_eq!(params, [text(r#"%5" 1/4%"#), text("%disquette%")]);
+        assert_eq!(parse_error(r#"title:="""#), "Missing value after title:");
+        assert_eq!(parse_error(r#""Le ""Grand Bleu"#), "Missing closing quote");
+    }
+
+    #[test]
+    fn negation() {
+        let (condition, params) = sql("shadoks -pompe");
+        assert!(condition.contains("AND NOT IFNULL(("), "{}", condition);
+        assert_eq!(params, [text("%shadoks%"), text("%pompe%")]);
+        let (condition, _) = sql("-actor:gabin");
+        assert!(condition.starts_with("NOT IFNULL((Film.CODE IN (SELECT CODE_FILM FROM Actor"));
+        // A lone - is ignored
+        assert_eq!(sql("shadoks -"), sql("shadoks"));
+        assert_eq!(sql(r#"- shadoks -"""#), sql("shadoks"));
+        assert_eq!(parse_error("-"), "Empty query");
+    }
+
+    #[test]
+    fn qualifiers() {
+        let (condition, params) = sql("actor:Gabin");
+        assert_eq!(
+            condition,
+            "(Film.CODE IN (SELECT CODE_FILM FROM Actor WHERE ACTOR_FOLDED LIKE ?1 ESCAPE '\\'))"
+        );
+        assert_eq!(params, [text("%gabin%")]);
+        let (condition, params) = sql(r#"actor:="Jean Gabin""#);
+        assert_eq!(
+            condition,
+            "(Film.CODE IN (SELECT CODE_FILM FROM Actor WHERE ACTOR_FOLDED = ?1))"
+        );
+        assert_eq!(params, [text("jean gabin")]);
+        let (condition, _) = sql("SERIES:shadoks");
+        assert!(condition.contains("Tape.SERIE_NAME_FOLDED LIKE ?1"));
+        let (condition, params) = sql("loc:elora_2");
+        assert_eq!(condition, "Tape.LOCATION = ?1 COLLATE NOCASE");
+        assert_eq!(params, [text("elora_2")]);
+        let (condition, params) = sql("type:DVD");
+        assert_eq!(condition, "Tape.TYPE = ?1");
+        assert_eq!(params, [Value::Integer(SupportType::Dvd as i64)]);
+    }
+
+    #[test]
+    fn year_ranges() {
+        assert_eq!(
+            sql("year:1950..1960"),
+            (
+                "Film.YEAR BETWEEN ?1 AND ?2".to_string(),
+                vec![Value::Integer(1950), Value::Integer(1960)]
+            )
+        );
+        assert_eq!(sql("year:1955"), sql("year:1955..1955"));
+        assert_eq!(sql("year:..1960"), ("Film.YEAR <= ?1".to_string(), vec![Value::Integer(1960)]));
+        assert_eq!(sql("year:1950.."), ("Film.YEAR >= ?1".to_string(), vec![Value::Integer(1950)]));
+    }
+
+    #[test]
+    fn sort_orders_end_with_a_unique_key() {
+        let orders = [
+            SortOrder::Title,
+            SortOrder::Year,
+            SortOrder::Duration,
+            SortOrder::Location,
+            SortOrder::RecentlyAdded,
+        ];
+        for order in orders {
+            let grouped = order.to_sql(true);
+            assert!(
+                grouped.ends_with(", Tape.CODE_TAPE") || grouped.ends_with(", Tape.CODE_TAPE DESC")
+            );
+            assert!(order.to_sql(false).starts_with(&format!("{}, Film.CODE", grouped)));
+        }
+    }
+
+    #[test]
+    fn plain_text() {
+        let query = SearchQuery::parse("Gabin  Morgan").unwrap();
+        assert_eq!(query.plain_text().as_deref(), Some("gabin morgan"));
+        assert_eq!(SearchQuery::parse("gabin year:1938").unwrap().plain_text(), None);
+        assert_eq!(SearchQuery::parse("gabin -morgan").unwrap().plain_text(), None);
+    }
+}
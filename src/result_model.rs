@@ -0,0 +1,144 @@
+//! The search results as a slint::Model, fetching the rows a page at a time,
+//! as the ListView scrolls to them. The pages are fetched on the search thread,
+//! the rows show empty until their page arrives.
+
+use crate::app::{AppWindow, ResultItemData};
+use crate::catalog::SearchHit;
+use crate::search_thread::{SearchThread, SearchTicket};
+use crate::slint_adapter::result_item;
+use crate::sqlsearch::SearchResults;
+use slint::Model;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Number of rows fetched at once
+pub const PAGE_SIZE: usize = 100;
+/// Pages kept in memory, the ones furthest from the current row are dropped first
+const MAX_CACHED_PAGES: usize = 10;
+
+pub struct ResultModel {
+    results: Arc<SearchResults>,
+    /// The search that found `results`, the pages are fetched as its jobs
+    ticket: SearchTicket,
+    search_thread: Rc<SearchThread>,
+    pages: RefCell<HashMap<usize, Vec<ResultItemData>>>,
+    /// The pages being fetched
+    requested: RefCell<HashSet<usize>>,
+    /// To find this model again when a page arrives
+    ui: slint::Weak<AppWindow>,
+    notify: slint::ModelNotify,
+}
+
+impl ResultModel {
+    /// `first_page` was fetched along with `results`, by the search.
+    pub fn new(
+        results: SearchResults,
+        first_page: Vec<SearchHit>,
+        ticket: SearchTicket,
+        search_thread: Rc<SearchThread>,
+        ui: slint::Weak<AppWindow>,
+    ) -> ResultModel {
+        let mut pages = HashMap::new();
+        if !first_page.is_empty() {
+            pages.insert(0, first_page.into_iter().map(result_item).collect());
+        }
+        ResultModel {
+            results: Arc::new(results),
+            ticket,
+            search_thread,
+            pages: RefCell::new(pages),
+            requested: RefCell::new(HashSet::new()),
+            ui,
+            notify: Default::default(),
+        }
+    }
+
+    fn request_page(&self, page: usize) {
+        if !self.requested.borrow_mut().insert(page) {
+            return;
+        }
+        let results = self.results.clone();
+        let ui_handle = self.ui.clone();
+        self.search_thread.run(&self.ticket, move |catalog, _| {
+            let hits = catalog.hits(&results, page * PAGE_SIZE, PAGE_SIZE);
+            let _ = slint::invoke_from_event_loop(move || {
+                let Some(ui) = ui_handle.upgrade() else {
+                    return;
+                };
+                let items = ui.get_result_items();
+                // Unless another search replaced the model in the meantime
+                if let Some(model) = items
+                    .as_any()
+                    .downcast_ref::<ResultModel>()
+                    .filter(|model| Arc::ptr_eq(&model.results, &results))
+                {
+                    model.page_fetched(&ui, page, hits);
+                }
+            });
+        });
+    }
+
+    fn page_fetched(
+        &self,
+        ui: &AppWindow,
+        page: usize,
+        hits: rusqlite::Result<Option<Vec<SearchHit>>>,
+    ) {
+        // Fetched or failed, it's requested again when its rows are shown again
+        self.requested.borrow_mut().remove(&page);
+        match hits {
+            Ok(Some(hits)) => {
+                let rows = page * PAGE_SIZE..page * PAGE_SIZE + hits.len();
+                {
+                    let mut pages = self.pages.borrow_mut();
+                    if pages.len() >= MAX_CACHED_PAGES {
+                        let furthest = pages.keys().copied().max_by_key(|p| p.abs_diff(page));
+                        if let Some(furthest) = furthest {
+                            pages.remove(&furthest);
+                        }
+                    }
+                    pages.insert(page, hits.into_iter().map(result_item).collect());
+                }
+                for row in rows {
+                    self.notify.row_changed(row);
+                }
+            }
+            Ok(None) => {
+                log::info!("The catalog changed, searching again");
+                ui.invoke_refresh_search();
+            }
+            Err(e) => log::warn!("Failed to fetch results {}..: {}", page * PAGE_SIZE, e),
+        }
+    }
+}
+
+impl Model for ResultModel {
+    type Data = ResultItemData;
+
+    fn row_count(&self) -> usize {
+        self.results.count()
+    }
+
+    fn row_data(&self, row: usize) -> Option<ResultItemData> {
+        if row >= self.results.count() {
+            return None;
+        }
+        let page = row / PAGE_SIZE;
+        let item =
+            self.pages.borrow().get(&page).and_then(|items| items.get(row % PAGE_SIZE).cloned());
+        if item.is_none() {
+            self.request_page(page);
+        }
+        Some(item.unwrap_or_default())
+    }
+
+    fn model_tracker(&self) -> &dyn slint::ModelTracker {
+        &self.notify
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
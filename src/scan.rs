@@ -0,0 +1,297 @@
+//! Scan an external HDD and emit a JSONL listing of its video files, one
+//! `merge::TapeRow` per line (`videofinder scan <mountpoint>`).
+//!
+//! This is the native version of scripts/scan_hdd.py. The filtering and
+//! title-shaping rules come from a rules file (see scan_rules.rs); with the
+//! default rules and --no-probe, for the same tree the rows are byte-identical
+//! to what the script writes (only the scanner name in the first "meta" line
+//! differs). By default the scanner also reads the duration, resolution and
+//! languages from the video files themselves (see probe.rs), and compares
+//! the new scan with the previous one (see scan_diff.rs).
+
+use crate::fingerprint::fingerprint;
+use crate::merge::{SliceMeta, TapeRow, read_slice};
+use crate::probe::probe;
+use crate::scan_diff::diff;
+use crate::scan_rules::Rules;
+use anyhow::Context;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const ID_FILE: &str = "id";
+const CANDIDATE_BASEDIRS: &[&str] = &["films", "films2", "films3"];
+
+/// The LOCATION of the HDD, from the first line of its `id` file.
+fn read_location(mountpoint: &Path) -> Result<String, anyhow::Error> {
+    let id_path = mountpoint.join(ID_FILE);
+    let content = fs::read_to_string(&id_path)
+        .with_context(|| format!("{:?} not found in {}", ID_FILE, mountpoint.display()))?;
+    let first = content.lines().next().unwrap_or_default().trim();
+    if first.is_empty() {
+        anyhow::bail!("{:?} is empty", ID_FILE);
+    }
+    // Mirror `sed -e 's/ /_/'` from update_filenames_in_kvideomanager.sh:
+    // only the first space becomes an underscore. The result is the LOCATION.
+    Ok(first.replacen(' ', "_", 1))
+}
+
+fn find_basedir(mountpoint: &Path) -> Result<&'static str, anyhow::Error> {
+    CANDIDATE_BASEDIRS.iter().copied().find(|dir| mountpoint.join(dir).is_dir()).ok_or_else(|| {
+        anyhow::anyhow!("none of {:?} found in {}", CANDIDATE_BASEDIRS, mountpoint.display())
+    })
+}
+
+/// Walk `dir` and add the paths of all files, relative to the scan root, to `entries`.
+/// Like os.walk, symlinks to directories are listed as directories but not followed,
+/// and unreadable directories are skipped.
+fn collect_paths(
+    dir: &Path,
+    relative_dir: &str,
+    entries: &mut BTreeSet<String>,
+) -> Result<(), anyhow::Error> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) => {
+            log::warn!("Skipping {}: {}", dir.display(), e);
+            return Ok(());
+        }
+    };
+    for entry in read_dir {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let relative =
+            if relative_dir.is_empty() { name } else { format!("{}/{}", relative_dir, name) };
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_paths(&entry.path(), &relative, entries)?;
+        } else if !(file_type.is_symlink() && entry.path().is_dir()) {
+            entries.insert(relative);
+        }
+    }
+    Ok(())
+}
+
+/// serde_json formatter matching Python's `json.dumps()` default separators
+/// (", " and ": "), so that the output is byte-identical to scan_hdd.py's.
+struct PythonFormatter;
+
+impl serde_json::ser::Formatter for PythonFormatter {
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        if first { Ok(()) } else { writer.write_all(b", ") }
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> std::io::Result<()> {
+        if first { Ok(()) } else { writer.write_all(b", ") }
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(b": ")
+    }
+}
+
+fn write_json_line<W: Write, T: serde::Serialize>(
+    out: &mut W,
+    value: &T,
+) -> Result<(), anyhow::Error> {
+    let mut ser = serde_json::Serializer::with_formatter(&mut *out, PythonFormatter);
+    value.serialize(&mut ser)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct MetaLine {
+    meta: SliceMeta,
+}
+
+#[derive(Default)]
+pub struct ScanOptions {
+    /// Where to write the JSONL, `<LOCATION>.jsonl` at the root of the HDD by default.
+    pub output: Option<PathBuf>,
+    /// Rules file to use instead of the built-in rules (src/scan_rules.json).
+    pub rules: Option<PathBuf>,
+    /// Print each skipped file to stderr.
+    pub verbose: bool,
+    /// Don't write anything, print what each rule does to each file instead.
+    pub dry_run: bool,
+    /// Don't read the files (container headers, size and fingerprint),
+    /// only use what the rules get from the file names, like scan_hdd.py.
+    pub no_probe: bool,
+    /// Previous slice to compare the new scan with, `output` by default.
+    pub previous: Option<PathBuf>,
+    /// Also write the comparison with the previous slice to this file, as JSON.
+    pub diff_json: Option<PathBuf>,
+}
+
+/// Scan the HDD mounted at `mountpoint` and write its JSONL slice.
+pub fn scan_hdd(mountpoint: &Path, options: ScanOptions) -> Result<(), anyhow::Error> {
+    let rules = Rules::load(options.rules.as_deref())?;
+    let location = read_location(mountpoint)?;
+    let basedir = find_basedir(mountpoint)?;
+
+    let mut all_paths = BTreeSet::new();
+    if options.dry_run {
+        collect_paths(&mountpoint.join(basedir), "", &mut all_paths)?;
+        for path in &all_paths {
+            let mut trace = Vec::new();
+            let shaped = rules.shape(path, &all_paths, Some(&mut trace));
+            println!("{}", path);
+            for line in trace {
+                println!("    {}", line);
+            }
+            match shaped {
+                Some(shaped) => println!("    => {:?}", shaped.title),
+                None => println!("    => skipped"),
+            }
+        }
+        return Ok(());
+    }
+
+    let output = options.output.unwrap_or_else(|| mountpoint.join(format!("{}.jsonl", location)));
+
+    eprintln!("Scanning {}/ as location={} -> {}", basedir, location, output.display());
+
+    let scan_root = mountpoint.join(basedir);
+    collect_paths(&scan_root, "", &mut all_paths)?;
+
+    let mut records = Vec::new();
+    let mut skipped = 0;
+    for path in &all_paths {
+        match rules.shape(path, &all_paths, None) {
+            Some(shaped) => {
+                let mut record = TapeRow {
+                    path: path.clone(),
+                    title: shaped.title,
+                    location: location.clone(),
+                    shelf: 1,
+                    row: 1,
+                    position: 1,
+                    type_: crate::enums::SupportType::ComputerFile as i32,
+                    date_purchase: shaped.date_purchase,
+                    duration: shaped.duration,
+                    width: None,
+                    height: None,
+                    languages: Vec::new(),
+                    size: None,
+                    fingerprint: None,
+                };
+                if !options.no_probe {
+                    let file_path = scan_root.join(path);
+                    // Falls back to the duration from the file name if the container can't be read.
+                    if let Some(info) = probe(&file_path) {
+                        record.duration = info.duration_minutes().unwrap_or(record.duration);
+                        record.width = info.width;
+                        record.height = info.height;
+                        record.languages = info.languages;
+                    }
+                    if let Some((size, fingerprint)) = fingerprint(&file_path) {
+                        record.size = Some(size);
+                        record.fingerprint = Some(fingerprint);
+                    }
+                }
+                records.push(record);
+            }
+            None => {
+                skipped += 1;
+                if options.verbose {
+                    eprintln!("skip: {}", path);
+                }
+            }
+        }
+    }
+
+    // Compare with the previous scan before overwriting it.
+    let previous = options.previous.unwrap_or_else(|| output.clone());
+    if previous.exists() {
+        match read_slice(&previous) {
+            Ok((_, previous_records)) => {
+                let diff = diff(&previous_records, &records);
+                eprint!("{}", diff.to_text());
+                if let Some(diff_json) = &options.diff_json {
+                    let json = serde_json::to_string_pretty(&diff)?;
+                    fs::write(diff_json, json + "\n")
+                        .with_context(|| format!("writing {}", diff_json.display()))?;
+                }
+            }
+            Err(e) => log::warn!("Not comparing with {}: {:#}", previous.display(), e),
+        }
+    }
+
+    let file = File::create(&output).with_context(|| format!("creating {}", output.display()))?;
+    let mut out = BufWriter::new(file);
+    let meta = SliceMeta {
+        scan_date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+        scanner: format!("videofinder {}", env!("CARGO_PKG_VERSION")),
+    };
+    write_json_line(&mut out, &MetaLine { meta })?;
+    for record in &records {
+        write_json_line(&mut out, record)?;
+    }
+    out.flush()?;
+
+    eprintln!("Wrote {} entries, skipped {} files -> {}", records.len(), skipped, output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What scripts/scan_hdd.py writes for the tree below, after its "meta" line
+    const SCAN_HDD_PY_ROWS: &str = r#"{"path": "Concert-05062001.avi", "title": "Concert", "location": "ELORA_9", "shelf": 1, "row": 1, "position": 1, "type": 4, "date_purchase": "2001-06-05", "duration": 0}
+{"path": "Doc/Arte - 12-03-2019 20h55 1h30 (123).mp4", "title": "Arte", "location": "ELORA_9", "shelf": 1, "row": 1, "position": 1, "type": 4, "date_purchase": "2019-03-12", "duration": 90}
+{"path": "Doc/Loin.mpg", "title": "Loin", "location": "ELORA_9", "shelf": 1, "row": 1, "position": 1, "type": 4, "date_purchase": "", "duration": 0}
+{"path": "Expo 03-04-2015.mp4", "title": "Expo ", "location": "ELORA_9", "shelf": 1, "row": 1, "position": 1, "type": 4, "date_purchase": "2015-04-03", "duration": 0}
+{"path": "Le Mépris.avi", "title": "Le Mépris", "location": "ELORA_9", "shelf": 1, "row": 1, "position": 1, "type": 4, "date_purchase": "", "duration": 0}
+{"path": "Series/Les Shadoks/S01E01.mkv", "title": "Les Shadoks: S01E01", "location": "ELORA_9", "shelf": 1, "row": 1, "position": 1, "type": 4, "date_purchase": "", "duration": 0}
+{"path": "unnamed 01-02-2020 21h00 0h45.mkv", "title": "unnamed 01-02-2020 21h00 0h45", "location": "ELORA_9", "shelf": 1, "row": 1, "position": 1, "type": 4, "date_purchase": "2020-02-01", "duration": 45}
+{"path": "Été/Plage.m2ts", "title": "Plage", "location": "ELORA_9", "shelf": 1, "row": 1, "position": 1, "type": 4, "date_purchase": "", "duration": 0}
+"#;
+
+    #[test]
+    fn default_rules_match_scan_hdd_py() {
+        let hdd = tempfile::tempdir().unwrap();
+        fs::write(hdd.path().join(ID_FILE), "ELORA 9\n").unwrap();
+        let files = [
+            "Le Mépris.avi",
+            "Doc/Arte - 12-03-2019 20h55 1h30 (123).mpg", // the .mp4 is preferred
+            "Doc/Arte - 12-03-2019 20h55 1h30 (123).mp4",
+            "Doc/Loin.mpg",
+            "unnamed 01-02-2020 21h00 0h45.mkv",
+            "Concert-05062001.avi",
+            "Expo 03-04-2015.mp4",
+            "Series/Les Shadoks/S01E01.mkv",
+            "Oiseaux/merle.avi",
+            "bin/x.avi",
+            "notes.txt",
+            "README",
+            "weird.mp4.part",
+            "Été/Plage.m2ts",
+        ];
+        for file in files {
+            let path = hdd.path().join("films").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        let output = hdd.path().join("out.jsonl");
+        let options =
+            ScanOptions { output: Some(output.clone()), no_probe: true, ..Default::default() };
+        scan_hdd(hdd.path(), options).unwrap();
+
+        let written = fs::read_to_string(output).unwrap();
+        let (meta, rows) = written.split_once('\n').unwrap();
+        assert!(meta.starts_with(r#"{"meta": {"scan_date": ""#));
+        assert_eq!(rows, SCAN_HDD_PY_ROWS);
+    }
+}
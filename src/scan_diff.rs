@@ -0,0 +1,120 @@
+//! Compare a new scan of an HDD with the previous JSONL slice of the same
+//! HDD, to notice files that were added, removed (possibly by accident) or
+//! renamed since the previous scan.
+
+use crate::merge::TapeRow;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+#[derive(Serialize)]
+pub struct Renamed {
+    pub from: String,
+    pub to: String,
+    /// "content" if the fingerprints are identical, "title" otherwise.
+    pub matched_by: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct ScanDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub renamed: Vec<Renamed>,
+}
+
+impl ScanDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+    }
+
+    /// Human-readable report. Removed files come first, they're the ones to check.
+    pub fn to_text(&self) -> String {
+        if self.is_empty() {
+            return "No changes since the previous scan\n".to_string();
+        }
+        let mut text = format!(
+            "{} removed, {} added, {} renamed since the previous scan\n",
+            self.removed.len(),
+            self.added.len(),
+            self.renamed.len()
+        );
+        for path in &self.removed {
+            let _ = writeln!(text, "  - {}", path);
+        }
+        for path in &self.added {
+            let _ = writeln!(text, "  + {}", path);
+        }
+        for renamed in &self.renamed {
+            let _ = writeln!(
+                text,
+                "  ~ {} -> {} (same {})",
+                renamed.from, renamed.to, renamed.matched_by
+            );
+        }
+        text
+    }
+}
+
+/// Key identifying the content of a file, when the scanner could read it.
+/// Empty files all look the same, they can't be matched this way.
+fn content_key(row: &TapeRow) -> Option<&str> {
+    row.fingerprint.as_deref().filter(|_| row.size.unwrap_or(0) > 0)
+}
+
+/// Pair each of `removed` with one of `added` having the same key, if any.
+/// The paired rows are taken out of both lists.
+fn pair_by<'a, K: std::hash::Hash + Eq>(
+    removed: &mut Vec<&'a TapeRow>,
+    added: &mut Vec<&'a TapeRow>,
+    key: impl Fn(&'a TapeRow) -> Option<K>,
+    matched_by: &'static str,
+    renamed: &mut Vec<Renamed>,
+) {
+    let mut added_by_key: HashMap<K, Vec<&'a TapeRow>> = HashMap::new();
+    for row in added.iter().rev() {
+        if let Some(k) = key(row) {
+            added_by_key.entry(k).or_default().push(row);
+        }
+    }
+    let mut paired: HashSet<&str> = HashSet::new();
+    removed.retain(|old| {
+        let new = key(old).and_then(|k| added_by_key.get_mut(&k)).and_then(|rows| rows.pop());
+        match new {
+            Some(new) => {
+                paired.insert(&new.path);
+                renamed.push(Renamed { from: old.path.clone(), to: new.path.clone(), matched_by });
+                false
+            }
+            None => true,
+        }
+    });
+    added.retain(|new| !paired.contains(new.path.as_str()));
+}
+
+/// Compare the rows of the previous slice of an HDD with the rows of the new scan.
+/// A removed file and an added file are considered a rename if they have the same
+/// fingerprint, or failing that, the same title.
+pub fn diff(previous: &[TapeRow], current: &[TapeRow]) -> ScanDiff {
+    let previous_paths: HashSet<&str> = previous.iter().map(|row| row.path.as_str()).collect();
+    let current_paths: HashSet<&str> = current.iter().map(|row| row.path.as_str()).collect();
+    let mut removed: Vec<&TapeRow> =
+        previous.iter().filter(|row| !current_paths.contains(row.path.as_str())).collect();
+    let mut added: Vec<&TapeRow> =
+        current.iter().filter(|row| !previous_paths.contains(row.path.as_str())).collect();
+
+    let mut renamed = Vec::new();
+    pair_by(&mut removed, &mut added, content_key, "content", &mut renamed);
+    pair_by(
+        &mut removed,
+        &mut added,
+        |row| Some(row.title.as_str()).filter(|title| !title.is_empty()),
+        "title",
+        &mut renamed,
+    );
+
+    ScanDiff {
+        added: added.into_iter().map(|row| row.path.clone()).collect(),
+        removed: removed.into_iter().map(|row| row.path.clone()).collect(),
+        renamed,
+    }
+}
@@ -0,0 +1,336 @@
+//! Title-shaping rules for the HDD scanner, loaded from a JSON rules file.
+//!
+//! The file lists the video extensions, which extension to prefer when two
+//! files share the same stem, and an ordered list of rules. The title starts
+//! as the path without its extension, then each rule does one of:
+//! - `"skip": "<regex>"`: skip the files whose path matches.
+//! - `"match": "<regex>"`: match the title. The named groups `day`, `month` and
+//!   `year` set the date, `hours` and `minutes` set the duration. `"strip"`
+//!   removes the match from the title (`"match"`) or every occurrence of the
+//!   matched text (`"all"`), unless the title matches `"keep_title_if"`.
+//!   Among the rules sharing the same `"group"`, only the first match applies.
+//! - `"basename": true`: keep only the file name part of the title.
+//! - `"prefix": "<regex>"`: if the path matches, prepend `"with"` to the title
+//!   (`${1}` etc. refer to the groups of the regex). With `"series": true`, the
+//!   matching files are episodes of the series named by group 1 (see episode.rs).
+//!
+//! src/scan_rules.json holds the default rules, which reproduce scan_hdd.py.
+
+use anyhow::Context;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::LazyLock;
+
+const DEFAULT_RULES: &str = include_str!("scan_rules.json");
+
+static EXTENSION_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9]+$").unwrap());
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum Strip {
+    #[default]
+    None,
+    Match,
+    All,
+}
+
+/// One rule as written in the rules file, see the module documentation.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    name: Option<String>,
+    skip: Option<String>,
+    #[serde(rename = "match")]
+    match_: Option<String>,
+    #[serde(default)]
+    strip: Strip,
+    keep_title_if: Option<String>,
+    group: Option<String>,
+    #[serde(default)]
+    basename: bool,
+    prefix: Option<String>,
+    #[serde(default)]
+    with: String,
+    #[serde(default)]
+    series: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    extensions: Vec<String>,
+    #[serde(default)]
+    prefer: HashMap<String, String>,
+    rules: Vec<RuleSpec>,
+}
+
+enum Action {
+    Skip(Regex),
+    Match { regex: Regex, strip: Strip, keep_title_if: Option<Regex>, group: Option<String> },
+    Basename,
+    Prefix { regex: Regex, with: String, series: bool },
+}
+
+struct Rule {
+    name: String,
+    action: Action,
+}
+
+/// A file under a series folder, as found by the `"series"` prefix rule.
+pub struct SeriesPath<'a> {
+    pub name: String,
+    /// What the rule prepended to the title
+    pub title_prefix: String,
+    /// The rest of the path, after the series folder
+    pub rest: &'a str,
+}
+
+/// Title, date and duration derived from a file path by the rules.
+pub struct Shaped {
+    pub title: String,
+    pub date_purchase: String,
+    pub duration: i32,
+}
+
+pub struct Rules {
+    extensions: Vec<String>,
+    prefer: HashMap<String, String>,
+    rules: Vec<Rule>,
+}
+
+fn compile(regex: &str, rule_name: &str) -> Result<Regex, anyhow::Error> {
+    Regex::new(regex).with_context(|| format!("invalid regex in rule {:?}", rule_name))
+}
+
+impl Rules {
+    /// Load the rules from `path`, or the built-in default rules if None.
+    pub fn load(path: Option<&Path>) -> Result<Rules, anyhow::Error> {
+        match path {
+            Some(path) => {
+                let json = std::fs::read_to_string(path)
+                    .with_context(|| format!("reading {}", path.display()))?;
+                Self::parse(&json).with_context(|| format!("loading rules {}", path.display()))
+            }
+            None => Self::parse(DEFAULT_RULES),
+        }
+    }
+
+    pub(crate) fn parse(json: &str) -> Result<Rules, anyhow::Error> {
+        let file: RulesFile = serde_json::from_str(json)?;
+        let mut rules = Vec::with_capacity(file.rules.len());
+        for (index, spec) in file.rules.into_iter().enumerate() {
+            let name = spec.name.unwrap_or_else(|| format!("rule {}", index + 1));
+            let action = match (spec.skip, spec.match_, spec.basename, spec.prefix) {
+                (Some(skip), None, false, None) => Action::Skip(compile(&skip, &name)?),
+                (None, Some(match_), false, None) => Action::Match {
+                    regex: compile(&match_, &name)?,
+                    strip: spec.strip,
+                    keep_title_if: match spec.keep_title_if {
+                        Some(keep) => Some(compile(&keep, &name)?),
+                        None => None,
+                    },
+                    group: spec.group,
+                },
+                (None, None, true, None) => Action::Basename,
+                (None, None, false, Some(prefix)) => {
+                    let regex = compile(&prefix, &name)?;
+                    if spec.series && regex.captures_len() < 2 {
+                        anyhow::bail!("series rule {:?} must capture the series name", name);
+                    }
+                    Action::Prefix { regex, with: spec.with, series: spec.series }
+                }
+                _ => anyhow::bail!(
+                    "rule {:?} must have exactly one of skip, match, basename or prefix",
+                    name
+                ),
+            };
+            if spec.series && !matches!(action, Action::Prefix { .. }) {
+                anyhow::bail!("only a prefix rule can be a series rule, not {:?}", name);
+            }
+            rules.push(Rule { name, action });
+        }
+        Ok(Rules { extensions: file.extensions, prefer: file.prefer, rules })
+    }
+
+    /// The series of the file at `path`, according to the first `"series"` rule
+    /// whose regex matches it.
+    pub fn series<'a>(&self, path: &'a str) -> Option<SeriesPath<'a>> {
+        self.rules.iter().find_map(|rule| match &rule.action {
+            Action::Prefix { regex, with, series: true } => {
+                let caps = regex.captures(path)?;
+                let mut title_prefix = String::new();
+                caps.expand(with, &mut title_prefix);
+                Some(SeriesPath {
+                    name: caps.get(1)?.as_str().to_string(),
+                    title_prefix,
+                    rest: &path[caps.get(0)?.end()..],
+                })
+            }
+            _ => None,
+        })
+    }
+
+    /// Apply the rules to the file at `path` (one of `all_paths`).
+    /// Returns None if the file should be skipped. When `trace` is given,
+    /// a line is appended to it for every rule that applied (for dry runs).
+    pub fn shape(
+        &self,
+        path: &str,
+        all_paths: &BTreeSet<String>,
+        mut trace: Option<&mut Vec<String>>,
+    ) -> Option<Shaped> {
+        let mut note = |line: String| {
+            if let Some(trace) = trace.as_deref_mut() {
+                trace.push(line);
+            }
+        };
+
+        let Some((stem, ext)) = path.rsplit_once('.') else {
+            note("no extension".to_string());
+            return None;
+        };
+        if !EXTENSION_PATTERN.is_match(ext) || !self.extensions.iter().any(|e| e == ext) {
+            note(format!("{:?} is not a video extension", ext));
+            return None;
+        }
+        let preferred = self.prefer.get(ext);
+        if let Some(preferred) =
+            preferred.filter(|preferred| all_paths.contains(&format!("{}.{}", stem, preferred)))
+        {
+            note(format!("the .{} version is preferred", preferred));
+            return None;
+        }
+
+        let mut shaped =
+            Shaped { title: stem.to_string(), date_purchase: String::new(), duration: 0 };
+        let mut matched_groups: Vec<&str> = Vec::new();
+        for rule in &self.rules {
+            match &rule.action {
+                Action::Skip(regex) => {
+                    if regex.is_match(path) {
+                        note(format!("{}: skipped", rule.name));
+                        return None;
+                    }
+                    continue;
+                }
+                Action::Match { regex, strip, keep_title_if, group } => {
+                    if group.as_deref().is_some_and(|g| matched_groups.contains(&g)) {
+                        continue;
+                    }
+                    let Some(caps) = regex.captures(&shaped.title) else {
+                        continue;
+                    };
+                    if let Some(group) = group {
+                        matched_groups.push(group);
+                    }
+                    if let (Some(day), Some(month), Some(year)) =
+                        (caps.name("day"), caps.name("month"), caps.name("year"))
+                    {
+                        shaped.date_purchase =
+                            format!("{}-{}-{}", year.as_str(), month.as_str(), day.as_str());
+                    }
+                    let number = |name: &str| {
+                        caps.name(name).and_then(|m| m.as_str().parse::<i32>().ok()).unwrap_or(0)
+                    };
+                    if caps.name("hours").is_some() || caps.name("minutes").is_some() {
+                        shaped.duration = number("hours") * 60 + number("minutes");
+                    }
+                    let keep = keep_title_if.as_ref().is_some_and(|k| k.is_match(&shaped.title));
+                    let whole = caps.get(0).unwrap();
+                    let title = match strip {
+                        _ if keep => None,
+                        Strip::None => None,
+                        Strip::Match => Some(format!(
+                            "{}{}",
+                            &shaped.title[..whole.start()],
+                            &shaped.title[whole.end()..]
+                        )),
+                        Strip::All => Some(shaped.title.replace(whole.as_str(), "")),
+                    };
+                    if let Some(title) = title {
+                        shaped.title = title;
+                    }
+                }
+                Action::Basename => {
+                    if let Some(slash) = shaped.title.rfind('/') {
+                        shaped.title.replace_range(..=slash, "");
+                    }
+                }
+                Action::Prefix { regex, with, .. } => {
+                    let Some(caps) = regex.captures(path) else {
+                        continue;
+                    };
+                    let mut prefix = String::new();
+                    caps.expand(with, &mut prefix);
+                    shaped.title.insert_str(0, &prefix);
+                }
+            }
+            note(format!(
+                "{}: {:?} (date {:?}, duration {})",
+                rule.name, shaped.title, shaped.date_purchase, shaped.duration
+            ));
+        }
+        Some(shaped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(rules: &Rules, path: &str) -> Option<(String, String, i32)> {
+        let all_paths = BTreeSet::from([path.to_string()]);
+        let shaped = rules.shape(path, &all_paths, None)?;
+        Some((shaped.title, shaped.date_purchase, shaped.duration))
+    }
+
+    #[test]
+    fn rule_with_two_actions_is_rejected() {
+        let json = r#"{"extensions": ["mkv"], "rules": [{"skip": "a", "match": "b"}]}"#;
+        let error = Rules::parse(json).err().unwrap();
+        assert!(error.to_string().contains("exactly one of"), "{}", error);
+        let json = r#"{"extensions": ["mkv"], "rules": [{"basename": true, "series": true}]}"#;
+        let error = Rules::parse(json).err().unwrap();
+        assert!(error.to_string().contains("only a prefix rule"), "{}", error);
+        let json = r#"{"extensions": ["mkv"], "rules": [{"prefix": "^TV/", "series": true}]}"#;
+        let error = Rules::parse(json).err().unwrap();
+        assert!(error.to_string().contains("series name"), "{}", error);
+    }
+
+    #[test]
+    fn invalid_regex_names_the_rule() {
+        let json = r#"{"extensions": ["mkv"], "rules": [{"name": "broken", "skip": "("}]}"#;
+        let error = Rules::parse(json).err().unwrap();
+        assert!(error.to_string().contains("\"broken\""), "{}", error);
+    }
+
+    #[test]
+    fn custom_rules() {
+        let json = r#"{
+            "extensions": ["mkv"],
+            "rules": [
+                {"match": "\\[(?P<hours>\\d)h(?P<minutes>\\d\\d)\\]", "strip": "match", "group": "d"},
+                {"match": "\\[\\d+\\]", "strip": "match", "group": "d"},
+                {"match": " \\(tmp\\)", "strip": "all", "keep_title_if": "^keep"},
+                {"basename": true},
+                {"prefix": "^Docs/([^/]+)/", "with": "${1} - "}
+            ]
+        }"#;
+        let rules = Rules::parse(json).unwrap();
+        // Only the first rule of the group applies
+        assert_eq!(
+            shape(&rules, "Docs/Arte/Volcans[1h05][42].mkv"),
+            Some(("Arte - Volcans[42]".to_string(), String::new(), 65))
+        );
+        assert_eq!(shape(&rules, "a (tmp)b (tmp).mkv"), Some(("ab".to_string(), String::new(), 0)));
+        assert_eq!(
+            shape(&rules, "keep (tmp).mkv"),
+            Some(("keep (tmp)".to_string(), String::new(), 0))
+        );
+        assert_eq!(shape(&rules, "Volcans.avi"), None);
+        assert_eq!(shape(&rules, "Volcans"), None);
+    }
+}
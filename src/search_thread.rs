@@ -0,0 +1,93 @@
+//! The searches run on a thread of their own, with its own catalog connection,
+//! so that typing doesn't wait for them. A new search interrupts the running one.
+//! The pages of results are fetched on that thread too (see result_model.rs),
+//! as jobs of their search, which are skipped once a newer search started.
+//! The suggestions run on another SearchThread, the same way.
+
+use crate::catalog::Catalog;
+use rusqlite::InterruptHandle;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+/// Identifies a search, to tell whether a newer one was started since.
+#[derive(Clone)]
+pub struct SearchTicket {
+    id: u64,
+    latest: Arc<AtomicU64>,
+}
+
+impl SearchTicket {
+    pub fn is_latest(&self) -> bool {
+        self.latest.load(Ordering::SeqCst) == self.id
+    }
+}
+
+/// Run on the search thread, with its catalog
+type Job = Box<dyn FnOnce(&mut Catalog, SearchTicket) + Send>;
+
+pub struct SearchThread {
+    jobs: mpsc::Sender<(SearchTicket, Job)>,
+    latest: Arc<AtomicU64>,
+    /// To interrupt the job running on the thread's connection
+    interrupt_handle: Arc<Mutex<Option<InterruptHandle>>>,
+}
+
+impl SearchThread {
+    pub fn start() -> SearchThread {
+        let (sender, receiver) = mpsc::channel::<(SearchTicket, Job)>();
+        let interrupt_handle = Arc::new(Mutex::new(None));
+        let thread_interrupt_handle = interrupt_handle.clone();
+        std::thread::spawn(move || {
+            let mut catalog = Catalog::default();
+            while let Ok((ticket, job)) = receiver.recv() {
+                // Only the jobs of the most recent search matter
+                if !ticket.is_latest() {
+                    continue;
+                }
+                if let (Ok(conn), Ok(mut handle)) =
+                    (catalog.connection(), thread_interrupt_handle.lock())
+                {
+                    *handle = Some(conn.get_interrupt_handle());
+                }
+                job(&mut catalog, ticket);
+            }
+        });
+        SearchThread { jobs: sender, latest: Arc::new(AtomicU64::new(0)), interrupt_handle }
+    }
+
+    /// Start a new search, running `job` on the thread, and interrupt the previous one.
+    /// The job should check its ticket again before displaying its results, on the UI thread.
+    pub fn search(&self, job: impl FnOnce(&mut Catalog, SearchTicket) + Send + 'static) {
+        let ticket = self.next_ticket();
+        self.send(ticket, Box::new(job));
+    }
+
+    /// Run `job` on the thread for the search of `ticket`, unless a newer search started.
+    pub fn run(
+        &self,
+        ticket: &SearchTicket,
+        job: impl FnOnce(&mut Catalog, SearchTicket) + Send + 'static,
+    ) {
+        self.send(ticket.clone(), Box::new(job));
+    }
+
+    /// Interrupt the running search and make sure its results won't be shown.
+    pub fn cancel(&self) {
+        self.next_ticket();
+    }
+
+    fn send(&self, ticket: SearchTicket, job: Job) {
+        if let Err(e) = self.jobs.send((ticket, job)) {
+            log::error!("Search thread is gone: {}", e);
+        }
+    }
+
+    fn next_ticket(&self) -> SearchTicket {
+        let id = self.latest.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Ok(Some(handle)) = self.interrupt_handle.lock().as_deref() {
+            handle.interrupt();
+        }
+        SearchTicket { id, latest: self.latest.clone() }
+    }
+}
@@ -0,0 +1,328 @@
+//! Conversions between the catalog types (see catalog.rs) and the Slint ones.
+
+use crate::app::{
+    EpisodeData, FolderEntryData, LoanData, LoanItemData, PersonalData, RecordWrapper,
+    ResultItemData, SearchFilterData, SeasonData, ShelfItemData, ShelfRowData, SuggestionData,
+    SupportData,
+};
+use crate::catalog::{FieldMatch, Film, Folder, SearchHit, Season, ShelfRow, Support, SupportRef};
+use crate::enums::SupportType;
+use crate::query::{Origin, SearchFilter};
+use crate::suggest::Suggestion;
+use crate::user_data::{CurrentLoan, Loan, UserData};
+use chrono::NaiveDate;
+use slint::{Color, SharedString, VecModel};
+use std::rc::Rc;
+
+pub fn result_item(hit: SearchHit) -> ResultItemData {
+    // The secondary line shows where the search matched, unless it's visible in the name already
+    let (match_field, match_before, match_text, match_after) = hit
+        .matches
+        .iter()
+        .find(|field_match| !hit.name.contains(&field_match.text))
+        .map(|FieldMatch { field, text, range }| {
+            (field.name(), &text[..range.start], &text[range.clone()], &text[range.end..])
+        })
+        .unwrap_or_default();
+    ResultItemData {
+        match_field: match_field.into(),
+        match_before: match_before.into(),
+        match_text: match_text.into(),
+        match_after: match_after.into(),
+        film_name: hit.name.into(),
+        support_color: color_for_support(hit.support_type, hit.origin, hit.on_loan),
+        support_type_text: letter_for_support_type(hit.support_type).into(),
+        film_code: hit.film_code.unwrap_or(0),
+        support_code: hit.support_code,
+        stale_age: hit.stale_since.map(stale_age_text).unwrap_or_default().into(),
+    }
+}
+
+/// `support` in the browse views, opening the details of `film_code` (0 for none)
+/// and `title` when clicked.
+pub fn support_data(support: SupportRef, film_code: i32, title: &str) -> SupportData {
+    SupportData {
+        letter: letter_for_support_type(support.support_type).into(),
+        color: color_for_support(support.support_type, support.origin, support.on_loan),
+        location: support.location.into(),
+        title: title.into(),
+        film_code,
+        support_code: support.code,
+    }
+}
+
+/// The seasons of a series, with rows for the missing episodes.
+pub fn season_data(season: Season) -> SeasonData {
+    let title = match season.number {
+        Some(number) => format!("Season {}", number),
+        None => "Other episodes".to_string(),
+    };
+    let summary = match season.missing.len() {
+        0 => format!("{} episodes", season.episodes.len()),
+        missing => format!("{} episodes, {} missing", season.episodes.len(), missing),
+    };
+    let mut episodes: Vec<EpisodeData> = season
+        .episodes
+        .into_iter()
+        .map(|episode| {
+            let film_code = episode.film_code.unwrap_or(0);
+            let supports: Vec<SupportData> = episode
+                .supports
+                .into_iter()
+                .map(|support| support_data(support, film_code, &episode.name))
+                .collect();
+            EpisodeData {
+                number: episode.number.unwrap_or(0),
+                name: episode.name.into(),
+                missing: false,
+                supports: Rc::new(VecModel::from(supports)).into(),
+            }
+        })
+        .collect();
+    episodes.extend(season.missing.into_iter().map(|number| EpisodeData {
+        number,
+        missing: true,
+        ..Default::default()
+    }));
+    // Unnumbered episodes (number 0) last
+    episodes.sort_by_key(|episode| (episode.number == 0, episode.number));
+    SeasonData {
+        title: title.into(),
+        summary: summary.into(),
+        episodes: Rc::new(VecModel::from(episodes)).into(),
+    }
+}
+
+pub fn shelf_row_data(row: ShelfRow) -> ShelfRowData {
+    let items: Vec<ShelfItemData> = row
+        .items
+        .into_iter()
+        .map(|item| ShelfItemData {
+            position: item.position,
+            support: support_data(item.support, item.film_code.unwrap_or(0), &item.title),
+        })
+        .collect();
+    ShelfRowData { shelf: row.shelf, row: row.row, items: Rc::new(VecModel::from(items)).into() }
+}
+
+/// "12 files, 10h30"
+pub fn folder_summary(folder: &Folder) -> String {
+    match folder.file_count {
+        1 => format!("1 file, {}", duration_text(folder.duration)),
+        count => format!("{} files, {}", count, duration_text(folder.duration)),
+    }
+}
+
+/// The subfolders of `folder`, then its files.
+pub fn folder_entries(folder: &Folder) -> Vec<FolderEntryData> {
+    let folders = folder.folders.iter().map(|subfolder| FolderEntryData {
+        name: subfolder.name.as_str().into(),
+        is_folder: true,
+        summary: folder_summary(subfolder).into(),
+        path: subfolder.path.as_str().into(),
+        ..Default::default()
+    });
+    let files = folder.files.iter().map(|file| FolderEntryData {
+        name: file.name.as_str().into(),
+        is_folder: false,
+        summary: duration_text(file.duration).into(),
+        support: support_data(file.support.clone(), 0, &file.title),
+        ..Default::default()
+    });
+    folders.chain(files).collect()
+}
+
+fn duration_text(minutes: i32) -> String {
+    format!("{}h{:02}", minutes / 60, minutes % 60)
+}
+
+/// The details of `support`, and of `film` unless it's an HDD file.
+pub fn record_wrapper(support: Support, film: Option<Film>) -> RecordWrapper {
+    let duplicates: Vec<SharedString> = support
+        .duplicates
+        .iter()
+        .map(|(location, path)| format!("{}: {}", location, path).into())
+        .collect();
+    let actors: Vec<SharedString> =
+        film.iter().flat_map(|film| &film.actors).map(|actor| actor.name.as_str().into()).collect();
+    RecordWrapper {
+        isComputerFile: support.support_type == SupportType::ComputerFile,
+        support_code: support.code,
+        shelf: support.shelf,
+        row: support.row,
+        position: support.position,
+        location: support.location.into(),
+        path: support.path.into(),
+        // For HDD/ComputerFile rows the Tape table carries the duration
+        // (set by the scanner from the container or the filename); for other supports
+        // it comes from Film.duration.
+        duration: film.as_ref().map_or(support.duration, |film| film.duration),
+        source: source_text(support.source, support.scan_date).into(),
+        // Read from the container by the scanner, for ComputerFile rows
+        resolution: match (support.width, support.height) {
+            (Some(width), Some(height)) => format!("{}x{}", width, height).into(),
+            _ => Default::default(),
+        },
+        languages: support.languages.unwrap_or_default().into(),
+        film_code: film.as_ref().map_or(0, |film| film.code),
+        year: film.as_ref().map_or(0, |film| film.year),
+        actors: Rc::new(VecModel::from(actors)).into(),
+        duplicates: Rc::new(VecModel::from(duplicates)).into(),
+    }
+}
+
+/// The state of the filter panel, as understood by the search.
+pub fn search_filter(data: &SearchFilterData) -> SearchFilter {
+    let bound = |text: &slint::SharedString| text.trim().parse::<i32>().ok();
+    let checked_types = [
+        (data.tape, SupportType::Tape),
+        (data.dvd, SupportType::Dvd),
+        (data.bluray, SupportType::Bluray),
+        (data.computer_file, SupportType::ComputerFile),
+    ];
+    let mut support_types: Vec<SupportType> =
+        checked_types.iter().filter(|(checked, _)| *checked).map(|(_, t)| *t).collect();
+    // Everything checked (or nothing): no need to filter
+    if support_types.len() == checked_types.len() {
+        support_types.clear();
+    }
+    SearchFilter {
+        support_types,
+        location: Some(data.location.to_string()).filter(|location| !location.is_empty()),
+        year: (bound(&data.year_from), bound(&data.year_to)),
+        duration: (bound(&data.duration_min), bound(&data.duration_max)),
+        origin: match data.origin {
+            1 => Some(Origin::Recorded),
+            2 => Some(Origin::Bought),
+            _ => None,
+        },
+        on_loan: match data.on_loan {
+            1 => Some(true),
+            2 => Some(false),
+            _ => None,
+        },
+        favourite_only: data.favourite_only,
+        watched: match data.watched {
+            1 => Some(true),
+            2 => Some(false),
+            _ => None,
+        },
+        min_rating: Some(data.min_rating).filter(|rating| *rating > 0),
+    }
+}
+
+pub fn personal_data(data: UserData) -> PersonalData {
+    PersonalData {
+        favourite: data.favourite,
+        watched: data.watched,
+        rating: data.rating.unwrap_or(0),
+        notes: data.notes.into(),
+    }
+}
+
+pub fn user_data(data: &PersonalData) -> UserData {
+    UserData {
+        favourite: data.favourite,
+        watched: data.watched,
+        rating: Some(data.rating).filter(|rating| *rating > 0),
+        notes: data.notes.to_string(),
+    }
+}
+
+pub fn suggestion_data(suggestion: Suggestion) -> SuggestionData {
+    SuggestionData {
+        kind: suggestion.field.name().into(),
+        query: suggestion.query().into(),
+        text: suggestion.text.into(),
+    }
+}
+
+/// The dates as shown to (and typed by) the user
+const DATE_FORMAT: &str = "%d/%m/%Y";
+
+pub fn loan_data(loan: &Loan) -> LoanData {
+    let format = |date: Option<NaiveDate>| {
+        date.map(|date| date.format(DATE_FORMAT).to_string()).unwrap_or_default().into()
+    };
+    LoanData {
+        borrower: loan.borrower.as_str().into(),
+        lent_on: format(Some(loan.lent_on)),
+        due_on: format(loan.due_on),
+        returned_on: format(loan.returned_on),
+        overdue: loan.is_overdue(),
+    }
+}
+
+pub fn loan_item(current: CurrentLoan) -> LoanItemData {
+    let support = match current.support {
+        Some(support) => support_data(support, current.film_code.unwrap_or(0), &current.title),
+        None => SupportData { title: current.title.into(), ..Default::default() },
+    };
+    LoanItemData { loan: loan_data(&current.loan), support }
+}
+
+/// The due date typed in the details window, None if it was left empty
+pub fn parse_due_date(text: &str) -> Result<Option<NaiveDate>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::parse_from_str(text, DATE_FORMAT)
+        .or_else(|_| NaiveDate::parse_from_str(text, "%Y-%m-%d"))
+        .map(Some)
+        .map_err(|_| format!("Invalid due date {:?}, expected DD/MM/YYYY", text))
+}
+
+fn letter_for_support_type(support_type: SupportType) -> &'static str {
+    match support_type {
+        SupportType::Tape => "C", // French ;)
+        SupportType::Dvd => "D",
+        SupportType::Bluray => "B",
+        SupportType::ComputerFile => "O", // French
+    }
+}
+
+fn color_for_support(support_type: SupportType, origin: String, on_loan: bool) -> Color {
+    let base_color = match support_type {
+        SupportType::Tape => {
+            // "enregistre" or "taped"
+            let is_taped = origin.starts_with('E') || origin.starts_with('T');
+            if is_taped {
+                Color::from_argb_encoded(0xFF1AE0FF) // light blue
+            } else {
+                Color::from_argb_encoded(0xFFFF1DFF) // pink
+            }
+
+            // TODO add color legend somewhere :-)
+        }
+        SupportType::Dvd => Color::from_argb_encoded(0xFF6DFF6B), // light green
+        SupportType::Bluray => Color::from_argb_encoded(0xFF000084), // dark blue
+        SupportType::ComputerFile => Color::from_argb_encoded(0xFFFFDCA8), // very light orange
+    };
+    if on_loan { base_color.brighter(0.5) } else { base_color }
+}
+
+/// "(stale, 3 days old)" for rows coming from a cached HDD slice
+/// that was last merged successfully at `stale_since`.
+fn stale_age_text(stale_since: i64) -> String {
+    let days = (chrono::Local::now().timestamp() - stale_since).max(0) / 86400;
+    match days {
+        0 => "(stale, less than a day old)".to_string(),
+        1 => "(stale, 1 day old)".to_string(),
+        _ => format!("(stale, {} days old)", days),
+    }
+}
+
+/// "from ELORA_2, scanned 03/09/2026", for the details view.
+fn source_text(source: Option<String>, scan_date: Option<String>) -> String {
+    let Some(source) = source else {
+        return String::new();
+    };
+    if source == crate::merge::QT_SOURCE {
+        return "from kvideomanager".to_string();
+    }
+    match scan_date.and_then(|d| chrono::NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok()) {
+        Some(date) => format!("from {}, scanned {}", source, date.format("%d/%m/%Y")),
+        None => format!("from {}", source),
+    }
+}
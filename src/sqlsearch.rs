@@ -1,13 +1,13 @@
-use crate::RecordWrapper;
-use crate::ResultItemData;
-use crate::download;
+use crate::catalog::{FieldMatch, FileStamp, Film, Person, SearchHit, Support};
 use crate::enums::FilmType;
 use crate::enums::SupportType;
-use std::rc::Rc;
+use crate::fold::find_folded;
+use crate::fuzzy::closest_terms;
+use crate::query::{SearchFilter, SearchQuery, SortOrder, TextField};
+use crate::user_data::{ON_LOAN_SQL, SUPPORT_KEY_SQL};
 
+use rusqlite::Connection;
 use rusqlite::types::{FromSql, FromSqlError, FromSqlResult};
-use rusqlite::{Connection, OpenFlags};
-use slint::VecModel;
 
 impl FromSql for SupportType {
     fn column_result(value: rusqlite::types::ValueRef<'_>) -> FromSqlResult<Self> {
@@ -23,40 +23,131 @@ impl FromSql for SupportType {
     }
 }
 
+/// The rows matching a search, fetched a page at a time (see Catalog::hits),
+/// so that a short query doesn't load thousands of them.
+pub struct SearchResults {
+    /// FROM and WHERE clauses, shared by the row and count statements
+    from_where: String,
+    params: Vec<rusqlite::types::Value>,
+    sort_order: SortOrder,
+    group_by_support: bool,
+    count: usize,
+    /// The searched texts, to find where each row matched them
+    text_terms: Vec<(TextField, String)>,
+    /// Nothing matched the query itself, the rows come from the typo-tolerant search
+    pub approximate: bool,
+    /// The merged.sqlite the rows were counted in, set by Catalog::search
+    pub(crate) stamp: FileStamp,
+}
+
 // do not use unwrap in this code, let errors propagate up to the UI
-// ResultItemData is a GUI type, defined in the slint code
-// Using this here is a bit arguable in terms of core/ui separation,
-// but avoids conversions & code duplication.
 pub fn sqlite_search(
-    text: String,
+    conn: &Connection,
+    query: &SearchQuery,
+    filter: &SearchFilter,
+    sort_order: SortOrder,
     group_by_support: bool,
-) -> rusqlite::Result<Vec<ResultItemData>> {
-    let conn =
-        Connection::open_with_flags(download::db_full_path(), OpenFlags::SQLITE_OPEN_READ_ONLY)?;
-
-    // Prepend/append '%'
-    let pattern = format!("%{}%", text);
-    log::debug!("  pattern={:?}", pattern);
-
-    let mut stmt = conn.prepare("SELECT Film.SERIE_NAME, Film.NAME, Film.TYPE, Tape.type, Film.SEASON, Film.EPISODE_NR, \
-          Tape.ORIGIN, Tape.ON_LOAN, Tape.code_tape, Film.code, Tape.TITLE \
-         FROM Tape LEFT JOIN (TapeFilm JOIN Film ON TapeFilm.code_film=Film.code) TapeFilm ON TapeFilm.code_tape=Tape.code_tape \
-         WHERE ( \
-           Tape.TITLE LIKE ?1 \
-           OR Film.SERIE_NAME LIKE ?2 \
-           OR Film.NAME LIKE ?3 \
-           OR Film.DIRECTOR LIKE ?4 \
-           OR Film.PRODUCER LIKE ?5 \
-           OR Film.COMPOSER LIKE ?6 \
-           OR Film.CODE IN (select CODE_FILM from Actor where ACTOR LIKE ?7) \
-         ) \
-         ORDER BY Film.SERIE_NAME, Film.NAME")?;
-
-    log::debug!("prepared, now running");
-
-    let iter = stmt.query_map(
-        [&pattern, &pattern, &pattern, &pattern, &pattern, &pattern, &pattern],
-        |row| {
+) -> rusqlite::Result<SearchResults> {
+    let results = SearchResults::new(conn, query, filter, sort_order, group_by_support)?;
+    let Some(text) = query.plain_text().filter(|_| results.count == 0) else {
+        return Ok(results);
+    };
+
+    let terms = {
+        let mut term_query = conn.prepare_cached("SELECT TERM FROM SearchTerm")?;
+        let candidates: Vec<String> =
+            term_query.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+        closest_terms(text.trim(), candidates.into_iter())
+    };
+    log::debug!("No exact match, approximate terms: {:?}", terms);
+    if terms.is_empty() {
+        return Ok(results);
+    }
+    let approximate_query = SearchQuery::from_alternatives(&terms);
+    let mut results =
+        SearchResults::new(conn, &approximate_query, filter, sort_order, group_by_support)?;
+    results.approximate = results.count > 0;
+    Ok(results)
+}
+
+impl SearchResults {
+    fn new(
+        conn: &Connection,
+        query: &SearchQuery,
+        filter: &SearchFilter,
+        sort_order: SortOrder,
+        group_by_support: bool,
+    ) -> rusqlite::Result<SearchResults> {
+        let (condition, params) = query.to_sql(filter);
+        log::debug!("  condition={:?} params={:?}", condition, params);
+        let from_where = format!(
+            "FROM Tape LEFT JOIN (TapeFilm JOIN Film ON TapeFilm.code_film=Film.code) TapeFilm \
+               ON TapeFilm.code_tape=Tape.code_tape \
+             LEFT JOIN Slice ON Slice.NAME=Tape.SOURCE \
+             WHERE {}",
+            condition
+        );
+
+        // Counted without fetching the rows. One row per support when grouping, see fetch().
+        let counted = if group_by_support { "DISTINCT Tape.code_tape" } else { "*" };
+        let count = conn
+            .prepare_cached(&format!("SELECT COUNT({}) {}", counted, from_where))?
+            .query_row(rusqlite::params_from_iter(&params), |row| row.get(0))?;
+
+        Ok(SearchResults {
+            from_where,
+            params,
+            sort_order,
+            group_by_support,
+            count,
+            text_terms: query.text_terms(),
+            approximate: false,
+            stamp: None,
+        })
+    }
+
+    /// Number of rows
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The rows from `offset` to `offset + limit`, in the sort order.
+    pub(crate) fn fetch(
+        &self,
+        conn: &Connection,
+        offset: usize,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<SearchHit>> {
+        let group_by_support = self.group_by_support;
+        // When grouping, the film columns come from the film with the smallest code on the support
+        // (SQLite takes the bare columns from the row where MIN() is reached).
+        let (film_code, group_by) = if group_by_support {
+            ("MIN(Film.code)", "GROUP BY Tape.code_tape")
+        } else {
+            ("Film.code", "")
+        };
+
+        // HDD episodes have no Film, their series/season/episode come from the Tape row (see episode.rs).
+        // SORT_NAME is the displayed name, as computed below, without leading article.
+        // The ORDER BY ends with the codes (see SortOrder::to_sql), so the pages don't overlap.
+        let mut stmt = conn.prepare_cached(&format!("SELECT COALESCE(Film.SERIE_NAME, Tape.SERIE_NAME) AS SERIE, \
+              COALESCE(Film.NAME, Tape.EPISODE_NAME) AS NAME, Film.TYPE, Tape.type, \
+              COALESCE(Film.SEASON, Tape.SEASON) AS SEASON, COALESCE(Film.EPISODE_NR, Tape.EPISODE_NR) AS EPISODE, \
+              Tape.ORIGIN, {}, Tape.code_tape, {}, Tape.TITLE, Slice.STALE_SINCE, \
+              Tape.SERIE_NAME IS NOT NULL, \
+              title_sort_key(CASE WHEN {} OR (Tape.TYPE = 4 AND Tape.SERIE_NAME IS NULL) THEN Tape.TITLE \
+                ELSE COALESCE(Film.SERIE_NAME, Tape.SERIE_NAME, Film.NAME, Tape.EPISODE_NAME, Tape.TITLE) END) AS SORT_NAME, \
+              Film.NAME, Film.DIRECTOR, Film.PRODUCER, Film.COMPOSER, Tape.PATH \
+             {} {} \
+             ORDER BY {} \
+             LIMIT ? OFFSET ?", *ON_LOAN_SQL, film_code, group_by_support, self.from_where, group_by, self.sort_order.to_sql(group_by_support)))?;
+        let mut params = self.params.clone();
+        params.push(rusqlite::types::Value::Integer(limit as i64));
+        params.push(rusqlite::types::Value::Integer(offset as i64));
+
+        log::debug!("prepared, now fetching {} rows from {}", limit, offset);
+
+        let iter = stmt.query_map(rusqlite::params_from_iter(&params), |row| {
             let serie_name = row.get::<_, Option<String>>(0)?;
             //log::debug!("serie_name: {:?}", serie_name);
             let name = row.get::<_, Option<String>>(1)?;
@@ -71,19 +162,36 @@ pub fn sqlite_search(
             let origin = row.get::<_, String>(6).unwrap_or_default();
             let on_loan = row.get::<_, bool>(7).unwrap_or(false);
             let support_code = row.get::<_, i32>(8).unwrap_or(0);
-            let film_code = row.get::<_, i32>(9).unwrap_or(0);
+            let film_code = row.get::<_, Option<i32>>(9).unwrap_or(None);
+            let stale_since = row.get::<_, Option<i64>>(11)?;
+            let is_hdd_episode = row.get::<_, bool>(12)?;
 
-            let film_name = {
-                if group_by_support || support_type == SupportType::ComputerFile {
+            // The searchable fields, in the order in which a match is looked for
+            let fields = [
+                (TextField::Title, Some(title.clone())),
+                (TextField::Title, row.get::<_, Option<String>>(14)?),
+                (TextField::Serie, serie_name.clone()),
+                (TextField::Director, row.get::<_, Option<String>>(15)?),
+                (TextField::Producer, row.get::<_, Option<String>>(16)?),
+                (TextField::Composer, row.get::<_, Option<String>>(17)?),
+                (TextField::Path, row.get::<_, Option<String>>(18)?),
+            ];
+            let matches = self.field_matches(conn, &fields, film_code)?;
+
+            let name = {
+                if group_by_support
+                    || (support_type == SupportType::ComputerFile && !is_hdd_episode)
+                {
                     title
-                } else if film_type == Some(FilmType::Television as i32) {
+                } else if film_type == Some(FilmType::Television as i32) || is_hdd_episode {
                     let mut film_name: String;
                     if let (Some(serie), Some(n)) = (&serie_name, &name) {
                         // Inside this block, 'serie' and 'n' are &String (references to String)
                         // You can dereference them (*serie, *n) or use .clone() if you need owned String
                         film_name = format!("{} -- {}", serie, n);
                     } else {
-                        film_name = name.unwrap_or_default();
+                        // HDD episodes without a name beyond their number
+                        film_name = name.or(serie_name).unwrap_or_default();
                     }
                     let maybe_season = row.get::<_, Option<i32>>(4).unwrap_or(None); // some are String("")
                     let maybe_episode = row.get::<_, Option<i32>>(5).unwrap_or(None);
@@ -101,92 +209,190 @@ pub fn sqlite_search(
                 }
             };
 
-            Ok(ResultItemData {
-                film_name: film_name.into(),
-                support_color: crate::enums::color_for_support(support_type, origin, on_loan),
-                support_type_text: crate::enums::letter_for_support_type(support_type).into(),
+            Ok(SearchHit {
+                name,
                 film_code,
                 support_code,
+                support_type,
+                origin,
+                on_loan,
+                stale_since,
+                matches,
             })
-        },
-    )?;
-
-    log::debug!("Done running");
+        })?;
 
-    //log::debug!("Search results {:?}", results);
-    let results: Vec<ResultItemData> = iter.collect::<rusqlite::Result<_>>()?;
+        log::debug!("Done fetching");
+        iter.collect()
+    }
 
-    if group_by_support {
-        let mut seen_supports = std::collections::HashSet::new();
-        Ok(results.into_iter().filter(|item| seen_supports.insert(item.support_code)).collect())
-    } else {
-        Ok(results)
+    /// Where each searched text is found in `fields`, or else in the actors of the film.
+    fn field_matches(
+        &self,
+        conn: &Connection,
+        fields: &[(TextField, Option<String>)],
+        film_code: Option<i32>,
+    ) -> rusqlite::Result<Vec<FieldMatch>> {
+        let mut actors: Option<Vec<String>> = None;
+        let mut matches = Vec::new();
+        for (searched_field, needle) in &self.text_terms {
+            // Only path: searches the path, see query.rs
+            let searched = |field: TextField| {
+                *searched_field == field
+                    || (*searched_field == TextField::Any && field != TextField::Path)
+            };
+            let found =
+                fields.iter().filter(|(field, _)| searched(*field)).find_map(|(field, text)| {
+                    let text = text.as_ref()?;
+                    let range = find_folded(text, needle)?;
+                    Some(FieldMatch { field: *field, text: text.clone(), range })
+                });
+            let found = match (found, film_code) {
+                (None, Some(film_code)) if searched(TextField::Actor) => {
+                    if actors.is_none() {
+                        let mut actor_query =
+                            conn.prepare_cached("SELECT ACTOR FROM Actor WHERE code_film=?1")?;
+                        let iter = actor_query.query_map([film_code], |row| row.get(0))?;
+                        actors = Some(iter.collect::<rusqlite::Result<_>>()?);
+                    }
+                    actors.iter().flatten().find_map(|actor| {
+                        let range = find_folded(actor, needle)?;
+                        Some(FieldMatch { field: TextField::Actor, text: actor.clone(), range })
+                    })
+                }
+                (found, _) => found,
+            };
+            matches.extend(found);
+        }
+        Ok(matches)
     }
 }
 
-pub fn sqlite_get_record(
-    film_code: i32,
-    support_code: i32,
-) -> rusqlite::Result<(RecordWrapper, Option<String>)> {
-    let conn =
-        Connection::open_with_flags(download::db_full_path(), OpenFlags::SQLITE_OPEN_READ_ONLY)?;
-    let mut support_query = conn.prepare(
-        "SELECT type, shelf, row, position, location, path, duration FROM Tape WHERE Tape.code_tape=?1",
+/// The locations of all the supports (shelves and HDD names), for the filter panel.
+pub fn sqlite_locations(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT DISTINCT LOCATION FROM Tape WHERE LOCATION != '' ORDER BY LOCATION",
     )?;
+    stmt.query_map([], |row| row.get(0))?.collect()
+}
+
+/// Layout version of the merged DB on disk, to detect one produced by an
+/// older version of merge::merge().
+pub fn sqlite_schema_version(conn: &Connection) -> rusqlite::Result<i32> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Files whose fingerprint appears in more than one location of the merged DB,
+/// as groups of (location, path), for `videofinder duplicates`.
+pub fn sqlite_duplicates(conn: &Connection) -> rusqlite::Result<Vec<Vec<(String, String)>>> {
+    let mut stmt = conn.prepare(
+        "SELECT fingerprint, location, path FROM Tape WHERE fingerprint IN \
+           (SELECT fingerprint FROM Tape WHERE fingerprint IS NOT NULL AND size > 0 \
+            GROUP BY fingerprint HAVING COUNT(DISTINCT location) > 1) \
+         ORDER BY fingerprint, location, path",
+    )?;
+    let mut groups: Vec<Vec<(String, String)>> = Vec::new();
+    let mut current_fingerprint: Option<String> = None;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let fingerprint: String = row.get(0)?;
+        if current_fingerprint.as_ref() != Some(&fingerprint) {
+            groups.push(Vec::new());
+            current_fingerprint = Some(fingerprint);
+        }
+        if let Some(group) = groups.last_mut() {
+            group.push((row.get(1)?, row.get(2)?));
+        }
+    }
+    Ok(groups)
+}
+
+pub fn sqlite_support(conn: &Connection, support_code: i32) -> rusqlite::Result<Support> {
+    let mut support_query = conn.prepare_cached(&format!(
+        "SELECT Tape.type, Tape.title, Tape.shelf, Tape.row, Tape.position, Tape.location, \
+           Tape.path, Tape.duration, Tape.source, Slice.scan_date, Tape.width, Tape.height, \
+           Tape.languages, Tape.fingerprint, {} \
+         FROM Tape LEFT JOIN Slice ON Slice.NAME=Tape.SOURCE WHERE Tape.code_tape=?1",
+        SUPPORT_KEY_SQL
+    ))?;
     log::info!("Doing support query for support code {}", support_code);
-    let mut record_wrapper = support_query.query_row([support_code], |row| {
+    let mut support = support_query.query_row([support_code], |row| {
         //log::info!("Support row: {:?}", row);
-        Ok(RecordWrapper {
-            isComputerFile: row.get::<_, SupportType>(0)? == SupportType::ComputerFile,
-            shelf: row.get(1)?,
-            row: row.get(2)?,
-            position: row.get(3)?,
-            location: row.get::<_, String>(4)?.into(),
-            path: row.get::<_, String>(5).unwrap_or(String::new()).into(),
-            // For HDD/ComputerFile rows the Tape table carries the duration
-            // (set by the scan script from the filename); for other supports
-            // it's overwritten below from Film.duration.
-            duration: row.get(6).unwrap_or(0),
-            // these will be set further below
-            film_code: 0,
-            year: 0,
-            actors: [].into(),
+        Ok(Support {
+            code: support_code,
+            support_type: row.get(0)?,
+            title: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            shelf: row.get(2)?,
+            row: row.get(3)?,
+            position: row.get(4)?,
+            location: row.get(5)?,
+            path: row.get::<_, String>(6).unwrap_or(String::new()),
+            duration: row.get(7).unwrap_or(0),
+            source: row.get(8)?,
+            scan_date: row.get(9)?,
+            width: row.get(10)?,
+            height: row.get(11)?,
+            languages: row.get(12)?,
+            fingerprint: row.get(13)?,
+            duplicates: Vec::new(), // set below
+            key: row.get(14)?,
         })
     })?;
-    if record_wrapper.isComputerFile {
-        return Ok((record_wrapper, None));
+    if let Some(fingerprint) = &support.fingerprint {
+        // The same file, copied on other disks (or twice on the same disk).
+        // Empty files all have the same fingerprint, they're not duplicates.
+        let mut duplicate_query = conn.prepare_cached(
+            "SELECT location, path FROM Tape WHERE fingerprint=?1 AND size > 0 AND code_tape!=?2 \
+             ORDER BY location, path",
+        )?;
+        let iter = duplicate_query
+            .query_map((fingerprint, support_code), |row| Ok((row.get(0)?, row.get(1)?)))?;
+        support.duplicates = iter.collect::<rusqlite::Result<_>>()?;
     }
+    Ok(support)
+}
 
-    let mut image_path: Option<String> = None;
-    if film_code != 0 {
-        log::info!("Doing film query for film code {}", film_code);
-        let mut film_query = conn.prepare("SELECT year, duration FROM Film WHERE Film.code=?1")?;
-        film_query.query_row([film_code], |row| {
-            //log::info!("Film row: {:?}", row);
-            record_wrapper.year = row.get(0).unwrap_or(0);
-            record_wrapper.duration = row.get(1).unwrap_or(0);
-            record_wrapper.film_code = film_code;
-            Ok(())
-        })?;
+pub fn sqlite_film(conn: &Connection, film_code: i32) -> rusqlite::Result<Film> {
+    log::info!("Doing film query for film code {}", film_code);
+    let mut film_query = conn.prepare_cached(
+        "SELECT name, serie_name, season, episode_nr, year, duration, director, producer, composer \
+         FROM Film WHERE Film.code=?1",
+    )?;
+    let mut film = film_query.query_row([film_code], |row| {
+        //log::info!("Film row: {:?}", row);
+        let person = |index| -> rusqlite::Result<Option<Person>> {
+            let name = row.get::<_, Option<String>>(index)?;
+            Ok(name.filter(|name| !name.is_empty()).map(|name| Person { name }))
+        };
+        Ok(Film {
+            code: film_code,
+            name: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+            serie_name: row.get(1)?,
+            season: row.get(2).unwrap_or(None), // some are String("")
+            episode_nr: row.get(3).unwrap_or(None),
+            year: row.get(4).unwrap_or(0),
+            duration: row.get(5).unwrap_or(0),
+            director: person(6)?,
+            producer: person(7)?,
+            composer: person(8)?,
+            actors: Vec::new(), // these will be set further below
+            image_path: None,
+        })
+    })?;
 
-        log::info!("Doing actor query for film code {}", film_code);
-        let mut actor_query = conn.prepare("SELECT ACTOR FROM Actor WHERE code_film=?1")?;
-        let iter = actor_query.query_map([film_code], |row| row.get::<_, String>(0))?;
-
-        //log::debug!("Actors {:?}", actors);
-        let actors: Vec<slint::SharedString> =
-            iter.map(|a| Ok(a?.into())).collect::<rusqlite::Result<_>>()?;
-        record_wrapper.actors = Rc::new(VecModel::from(actors)).into();
-
-        log::info!("Doing image query for film code {}", film_code);
-        let mut image_query = conn.prepare("SELECT N_IMAGE FROM Image WHERE code_film=?1")?;
-        let _ = image_query.query_row([film_code], |row| {
-            //log::debug!("Image row {:?}", row);
-            image_path = Some(row.get(0)?);
-            log::debug!("image_path: {:?}", image_path);
-            Ok(())
-        }); // no ? here, ignore errors
-    }
+    log::info!("Doing actor query for film code {}", film_code);
+    let mut actor_query = conn.prepare_cached("SELECT ACTOR FROM Actor WHERE code_film=?1")?;
+    let iter = actor_query.query_map([film_code], |row| Ok(Person { name: row.get(0)? }))?;
+    film.actors = iter.collect::<rusqlite::Result<_>>()?;
+    //log::debug!("Actors {:?}", film.actors);
+
+    log::info!("Doing image query for film code {}", film_code);
+    let mut image_query = conn.prepare_cached("SELECT N_IMAGE FROM Image WHERE code_film=?1")?;
+    let _ = image_query.query_row([film_code], |row| {
+        //log::debug!("Image row {:?}", row);
+        film.image_path = Some(row.get(0)?);
+        log::debug!("image_path: {:?}", film.image_path);
+        Ok(())
+    }); // no ? here, ignore errors
 
-    Ok((record_wrapper, image_path))
+    Ok(film)
 }
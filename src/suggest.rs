@@ -0,0 +1,148 @@
+//! The suggestions shown under the search field as we type: the series, film titles,
+//! actors and directors containing the text, each one leading to an exact search
+//! (see the `=` values in query.rs).
+
+use crate::fold::fold;
+use crate::query::{TextField, like_pattern};
+use rusqlite::Connection;
+
+/// The kinds of suggestions, in the order of the KIND column below
+const KINDS: [TextField; 4] =
+    [TextField::Serie, TextField::Title, TextField::Actor, TextField::Director];
+
+/// The text has to be that long (once folded) before suggesting anything
+const MIN_LENGTH: usize = 2;
+
+#[derive(Clone, Debug)]
+pub struct Suggestion {
+    pub field: TextField,
+    /// As written in the catalog
+    pub text: String,
+}
+
+impl Suggestion {
+    /// The query searching for exactly this series, title or person
+    pub fn query(&self) -> String {
+        // A quote is doubled inside quotes, see query.rs
+        format!("{}:=\"{}\"", self.field.name(), self.text.replace('"', "\"\""))
+    }
+}
+
+/// At most `limit` suggestions for `text`: the names starting with it first, then
+/// those with a word starting with it, then those containing it, the most frequent
+/// first. Nothing for the queries using the query language, e.g. `actor:gabin`.
+pub fn sqlite_suggestions(
+    conn: &Connection,
+    text: &str,
+    limit: usize,
+) -> rusqlite::Result<Vec<Suggestion>> {
+    if text.contains([':', '"']) || text.trim_start().starts_with('-') {
+        return Ok(Vec::new());
+    }
+    let folded = fold(text.trim());
+    if folded.chars().count() < MIN_LENGTH {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare_cached(
+        "SELECT KIND, NAME FROM ( \
+           SELECT 0 AS KIND, MIN(NAME) AS NAME, FOLDED, COUNT(*) AS FILMS FROM \
+             (SELECT SERIE_NAME AS NAME, SERIE_NAME_FOLDED AS FOLDED FROM Film \
+              UNION ALL SELECT SERIE_NAME, SERIE_NAME_FOLDED FROM Tape) \
+             WHERE FOLDED LIKE ?1 ESCAPE '\\' GROUP BY FOLDED \
+           UNION ALL SELECT 1, MIN(NAME), NAME_FOLDED, COUNT(*) FROM Film \
+             WHERE NAME_FOLDED LIKE ?1 ESCAPE '\\' GROUP BY NAME_FOLDED \
+           UNION ALL SELECT 2, MIN(ACTOR), ACTOR_FOLDED, COUNT(*) FROM Actor \
+             WHERE ACTOR_FOLDED LIKE ?1 ESCAPE '\\' GROUP BY ACTOR_FOLDED \
+           UNION ALL SELECT 3, MIN(DIRECTOR), DIRECTOR_FOLDED, COUNT(*) FROM Film \
+             WHERE DIRECTOR_FOLDED LIKE ?1 ESCAPE '\\' GROUP BY DIRECTOR_FOLDED) \
+         ORDER BY CASE WHEN instr(FOLDED, ?2) = 1 THEN 0 \
+             WHEN instr(FOLDED, ' ' || ?2) > 0 THEN 1 ELSE 2 END, \
+           FILMS DESC, KIND, title_sort_key(NAME) \
+         LIMIT ?3",
+    )?;
+    let rows = stmt.query_map((like_pattern(&folded), &folded, limit as i64), |row| {
+        Ok((row.get::<_, usize>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut suggestions = Vec::new();
+    for row in rows {
+        let (kind, text) = row?;
+        if let Some(field) = KINDS.get(kind) {
+            suggestions.push(Suggestion { field: *field, text });
+        }
+    }
+    Ok(suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::tests::merged_db;
+    use crate::query::{SearchFilter, SearchQuery, SortOrder};
+    use crate::sqlsearch::sqlite_search;
+
+    const FILMS: &str = "INSERT INTO Tape VALUES (1, 'DVD', 'ARMOIRE_1', 1, 1, 1, NULL, 2, '', 0, \
+           'A', 0); \
+         INSERT INTO TapeFilm VALUES (1, 1), (1, 2), (1, 3), (1, 4), (1, 5); \
+         INSERT INTO Film VALUES (1, 1981, 90, 0, NULL, NULL, NULL, 'Garde à vue', NULL, NULL, NULL); \
+         INSERT INTO Film VALUES (2, 2002, 97, 0, NULL, NULL, NULL, 'Irréversible', 'Gaspar Noé', \
+           NULL, NULL); \
+         INSERT INTO Film VALUES (3, 2018, 97, 0, NULL, NULL, NULL, 'Climax', 'Gaspar Noé', NULL, NULL); \
+         INSERT INTO Film VALUES (4, 1937, 114, 0, NULL, NULL, NULL, 'Mégane', NULL, NULL, NULL); \
+         INSERT INTO Film VALUES (5, 1988, 168, 0, NULL, NULL, NULL, 'Le \"Grand\" Bleu', NULL, NULL, NULL); \
+         INSERT INTO Actor VALUES (1, 'Jean Gabin');";
+
+    fn texts(suggestions: &[Suggestion]) -> Vec<&str> {
+        suggestions.iter().map(|suggestion| suggestion.text.as_str()).collect()
+    }
+
+    #[test]
+    fn ranking() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = merged_db(dir.path(), FILMS, &[]);
+        // Starting with it (the most frequent first), then a word starting with it, then containing it
+        let suggestions = sqlite_suggestions(&conn, "GA", 10).unwrap();
+        assert_eq!(texts(&suggestions), ["Gaspar Noé", "Garde à vue", "Jean Gabin", "Mégane"]);
+        let fields: Vec<_> = suggestions.iter().map(|suggestion| suggestion.field).collect();
+        assert_eq!(
+            fields,
+            [TextField::Director, TextField::Title, TextField::Actor, TextField::Title]
+        );
+        assert_eq!(
+            texts(&sqlite_suggestions(&conn, " ga", 2).unwrap()),
+            ["Gaspar Noé", "Garde à vue"]
+        );
+        assert_eq!(texts(&sqlite_suggestions(&conn, "noe", 10).unwrap()), ["Gaspar Noé"]);
+    }
+
+    #[test]
+    fn nothing_for_the_query_language() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = merged_db(dir.path(), FILMS, &[]);
+        for text in ["actor:ga", "\"garde", "-ga", " -ga", "g", " "] {
+            assert!(sqlite_suggestions(&conn, text, 10).unwrap().is_empty(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn query_finds_the_suggestion() {
+        let suggestion = Suggestion { field: TextField::Actor, text: "Jean Gabin".to_string() };
+        assert_eq!(suggestion.query(), r#"actor:="Jean Gabin""#);
+        let suggestion =
+            Suggestion { field: TextField::Title, text: r#"Le "Grand" Bleu"#.to_string() };
+        assert_eq!(suggestion.query(), r#"title:="Le ""Grand"" Bleu""#);
+
+        let dir = tempfile::tempdir().unwrap();
+        let conn = merged_db(dir.path(), FILMS, &[]);
+        let search = |suggestion: &Suggestion| {
+            let query = SearchQuery::parse(&suggestion.query()).unwrap();
+            let filter = SearchFilter::default();
+            sqlite_search(&conn, &query, &filter, SortOrder::Title, false).unwrap().count()
+        };
+        let suggestions = sqlite_suggestions(&conn, "gr", 10).unwrap();
+        assert_eq!(texts(&suggestions), [r#"Le "Grand" Bleu"#]);
+        assert_eq!(search(&suggestions[0]), 1);
+        let counts: Vec<_> =
+            sqlite_suggestions(&conn, "ga", 10).unwrap().iter().map(search).collect();
+        assert_eq!(counts, [2, 1, 1, 1]);
+    }
+}
@@ -0,0 +1,404 @@
+//! What we record ourselves about the films and supports: favourites, watched,
+//! ratings and notes, and the loans. It's kept in a DB of its own, since merged.sqlite
+//! is replaced by every sync.
+//!
+//! The HDD rows get a new CODE_TAPE at every merge, so the supports are identified
+//! by a key that survives it, see SUPPORT_KEY_SQL.
+
+use crate::catalog::SupportRef;
+use chrono::{Local, NaiveDate};
+use rusqlite::{Connection, OptionalExtension, Row};
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// Bumped whenever the layout of the user DB changes, see create_tables()
+const USER_SCHEMA_VERSION: i32 = 2;
+
+/// The stable identity of the support in the current Tape row: its code for the
+/// rows of the Qt DB (see merge::QT_SOURCE), its location and fingerprint for HDD
+/// files (which survive renames, while the copies of a file on several HDDs are
+/// still different supports), or else its location and path.
+pub const SUPPORT_KEY_SQL: &str = "CASE WHEN Tape.SOURCE = 'qt' THEN 'qt:' || Tape.CODE_TAPE \
+     WHEN Tape.FINGERPRINT IS NOT NULL AND Tape.SIZE > 0 \
+       THEN 'fp:' || Tape.LOCATION || '/' || Tape.FINGERPRINT \
+     ELSE 'hdd:' || Tape.LOCATION || '/' || Tape.PATH END";
+
+/// Whether the support of the current Tape row is lent: once we recorded a loan
+/// for it, our loans replace the ON_LOAN flag of the catalog.
+pub static ON_LOAN_SQL: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "IFNULL((SELECT MAX(L.RETURNED_ON IS NULL) FROM userdb.Loan L WHERE L.SUPPORT_KEY = ({})), \
+           IFNULL(Tape.ON_LOAN, 0))",
+        SUPPORT_KEY_SQL
+    )
+});
+
+/// A support lent to someone, see Catalog::lend
+#[derive(Clone, Debug)]
+pub struct Loan {
+    pub borrower: String,
+    pub lent_on: NaiveDate,
+    /// When we expect it back
+    pub due_on: Option<NaiveDate>,
+    /// None while it's still lent
+    pub returned_on: Option<NaiveDate>,
+}
+
+impl Loan {
+    pub fn is_overdue(&self) -> bool {
+        self.returned_on.is_none() && self.due_on.is_some_and(|due_on| due_on < today())
+    }
+}
+
+/// A support currently lent, for the list of loans
+#[derive(Clone, Debug)]
+pub struct CurrentLoan {
+    pub loan: Loan,
+    pub support_key: String,
+    /// None if the support isn't in the catalog anymore
+    pub support: Option<SupportRef>,
+    /// The support title, or its key if it isn't in the catalog anymore
+    pub title: String,
+    /// One of the films on the support
+    pub film_code: Option<i32>,
+}
+
+/// Our data about a film on a support
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UserData {
+    pub favourite: bool,
+    pub watched: bool,
+    /// From 1 to 5
+    pub rating: Option<i32>,
+    pub notes: String,
+}
+
+/// Open the user DB at `path`, creating it if needed.
+pub fn open_user_db(path: &Path) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    create_tables(&conn)?;
+    Ok(conn)
+}
+
+fn create_tables(conn: &Connection) -> rusqlite::Result<()> {
+    let version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version < 1 {
+        // FILM_CODE is 0 for the supports without a film, e.g. most HDD files
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS UserData (FILM_CODE INTEGER NOT NULL, \
+               SUPPORT_KEY TEXT NOT NULL, FAVOURITE INTEGER NOT NULL DEFAULT 0, \
+               WATCHED INTEGER NOT NULL DEFAULT 0, RATING INTEGER, NOTES TEXT NOT NULL DEFAULT '', \
+               PRIMARY KEY (FILM_CODE, SUPPORT_KEY)) WITHOUT ROWID;",
+        )?;
+    }
+    if version < 2 {
+        // Dates are "YYYY-MM-DD"
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS Loan (ID INTEGER PRIMARY KEY, SUPPORT_KEY TEXT NOT NULL, \
+               BORROWER TEXT NOT NULL, LENT_ON TEXT NOT NULL, DUE_ON TEXT, RETURNED_ON TEXT); \
+             CREATE INDEX IF NOT EXISTS LoanSupport ON Loan (SUPPORT_KEY);",
+        )?;
+    }
+    conn.pragma_update(None, "user_version", USER_SCHEMA_VERSION)
+}
+
+pub fn sqlite_user_data(
+    conn: &Connection,
+    film_code: i32,
+    support_key: &str,
+) -> rusqlite::Result<UserData> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT FAVOURITE, WATCHED, RATING, NOTES FROM UserData \
+         WHERE FILM_CODE=?1 AND SUPPORT_KEY=?2",
+    )?;
+    let data = stmt
+        .query_row((film_code, support_key), |row| {
+            Ok(UserData {
+                favourite: row.get(0)?,
+                watched: row.get(1)?,
+                rating: row.get(2)?,
+                notes: row.get(3)?,
+            })
+        })
+        .optional()?;
+    Ok(data.unwrap_or_default())
+}
+
+pub fn sqlite_set_user_data(
+    conn: &Connection,
+    film_code: i32,
+    support_key: &str,
+    data: &UserData,
+) -> rusqlite::Result<()> {
+    if *data == UserData::default() {
+        conn.prepare_cached("DELETE FROM UserData WHERE FILM_CODE=?1 AND SUPPORT_KEY=?2")?
+            .execute((film_code, support_key))?;
+        return Ok(());
+    }
+    conn.prepare_cached(
+        "INSERT OR REPLACE INTO UserData (FILM_CODE, SUPPORT_KEY, FAVOURITE, WATCHED, RATING, NOTES) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?
+    .execute((
+        film_code,
+        support_key,
+        data.favourite,
+        data.watched,
+        data.rating.filter(|rating| (1..=5).contains(rating)),
+        &data.notes,
+    ))?;
+    Ok(())
+}
+
+fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// The date in column `index`, None if it's NULL (or not a date)
+fn date(row: &Row, index: usize) -> rusqlite::Result<Option<NaiveDate>> {
+    let text: Option<String> = row.get(index)?;
+    Ok(text.and_then(|text| NaiveDate::parse_from_str(&text, "%Y-%m-%d").ok()))
+}
+
+/// The loan in the columns from `first`: BORROWER, LENT_ON, DUE_ON, RETURNED_ON
+fn loan(row: &Row, first: usize) -> rusqlite::Result<Loan> {
+    Ok(Loan {
+        borrower: row.get(first)?,
+        lent_on: date(row, first + 1)?.unwrap_or_default(),
+        due_on: date(row, first + 2)?,
+        returned_on: date(row, first + 3)?,
+    })
+}
+
+/// The loans of the support with the key `support_key`, the latest first.
+pub fn sqlite_loans(conn: &Connection, support_key: &str) -> rusqlite::Result<Vec<Loan>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT BORROWER, LENT_ON, DUE_ON, RETURNED_ON FROM Loan WHERE SUPPORT_KEY=?1 \
+         ORDER BY RETURNED_ON IS NOT NULL, LENT_ON DESC, ID DESC",
+    )?;
+    stmt.query_map([support_key], |row| loan(row, 0))?.collect()
+}
+
+pub fn sqlite_lend(
+    conn: &Connection,
+    support_key: &str,
+    borrower: &str,
+    due_on: Option<NaiveDate>,
+) -> rusqlite::Result<()> {
+    // Lending it again means it came back in the meantime
+    sqlite_return(conn, support_key)?;
+    conn.prepare_cached(
+        "INSERT INTO Loan (SUPPORT_KEY, BORROWER, LENT_ON, DUE_ON) VALUES (?1, ?2, ?3, ?4)",
+    )?
+    .execute((
+        support_key,
+        borrower,
+        today().to_string(),
+        due_on.map(|due_on| due_on.to_string()),
+    ))?;
+    Ok(())
+}
+
+pub fn sqlite_return(conn: &Connection, support_key: &str) -> rusqlite::Result<()> {
+    conn.prepare_cached(
+        "UPDATE Loan SET RETURNED_ON=?2 WHERE SUPPORT_KEY=?1 AND RETURNED_ON IS NULL",
+    )?
+    .execute((support_key, today().to_string()))?;
+    Ok(())
+}
+
+/// The supports currently lent, the ones expected back first first. On the connection
+/// to the merged DB, with the user DB attached (see catalog.rs).
+pub fn sqlite_current_loans(conn: &Connection) -> rusqlite::Result<Vec<CurrentLoan>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT L.BORROWER, L.LENT_ON, L.DUE_ON, L.RETURNED_ON, L.SUPPORT_KEY, \
+           Tape.CODE_TAPE, Tape.TYPE, Tape.LOCATION, Tape.ORIGIN, Tape.TITLE, \
+           (SELECT MIN(CODE_FILM) FROM TapeFilm WHERE TapeFilm.CODE_TAPE=Tape.CODE_TAPE) \
+         FROM userdb.Loan L LEFT JOIN Tape ON L.SUPPORT_KEY = ({}) \
+         WHERE L.RETURNED_ON IS NULL \
+         GROUP BY L.ID \
+         ORDER BY L.DUE_ON IS NULL, L.DUE_ON, L.LENT_ON",
+        SUPPORT_KEY_SQL
+    ))?;
+    stmt.query_map([], |row| {
+        let support_key: String = row.get(4)?;
+        let support = match row.get::<_, Option<i32>>(5)? {
+            Some(code) => Some(SupportRef {
+                code,
+                support_type: row.get(6)?,
+                location: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+                origin: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+                on_loan: true,
+            }),
+            None => None,
+        };
+        Ok(CurrentLoan {
+            loan: loan(row, 0)?,
+            title: row.get::<_, Option<String>>(9)?.unwrap_or_else(|| support_key.clone()),
+            support_key,
+            support,
+            film_code: row.get(10)?,
+        })
+    })?
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::tests::{hdd_file, merged_db, user_db};
+    use crate::merge::TapeRow;
+    use crate::sqlsearch::sqlite_support;
+
+    const TAPES: &str = "INSERT INTO Tape VALUES (1, 'Cassette 12', 'ARMOIRE_1', 2, 3, 4, NULL, 1, \
+           '', 0, 'E', 1); \
+         INSERT INTO Tape VALUES (2, 'Le Mépris', 'ARMOIRE_1', 2, 3, 5, NULL, 2, '', 0, 'A', 0); \
+         INSERT INTO TapeFilm VALUES (2, 7), (2, 4);";
+
+    fn on_loan(conn: &Connection, code: i32) -> bool {
+        conn.query_row(
+            &format!("SELECT {} FROM Tape WHERE CODE_TAPE=?1", *ON_LOAN_SQL),
+            [code],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    fn days(days: i64) -> NaiveDate {
+        today() + chrono::Duration::days(days)
+    }
+
+    #[test]
+    fn lend_and_return() {
+        let dir = tempfile::tempdir().unwrap();
+        let _conn = merged_db(dir.path(), TAPES, &[]);
+        let conn = open_user_db(&user_db(dir.path())).unwrap();
+        sqlite_lend(&conn, "qt:2", "Paul", Some(days(14))).unwrap();
+        let loans = sqlite_loans(&conn, "qt:2").unwrap();
+        assert_eq!(loans.len(), 1);
+        assert_eq!((loans[0].borrower.as_str(), loans[0].lent_on), ("Paul", today()));
+        assert_eq!((loans[0].due_on, loans[0].returned_on), (Some(days(14)), None));
+
+        // Lent again: the first loan ended, the new one comes first
+        sqlite_lend(&conn, "qt:2", "Marie", None).unwrap();
+        let loans = sqlite_loans(&conn, "qt:2").unwrap();
+        let borrowers: Vec<_> = loans.iter().map(|loan| loan.borrower.as_str()).collect();
+        assert_eq!(borrowers, ["Marie", "Paul"]);
+        assert_eq!((loans[0].returned_on, loans[1].returned_on), (None, Some(today())));
+
+        sqlite_return(&conn, "qt:2").unwrap();
+        let loans = sqlite_loans(&conn, "qt:2").unwrap();
+        assert!(loans.iter().all(|loan| loan.returned_on == Some(today())));
+        // Nothing to return
+        sqlite_return(&conn, "qt:1").unwrap();
+        assert!(sqlite_loans(&conn, "qt:1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn current_loans() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = merged_db(dir.path(), TAPES, &[]);
+        let user_conn = open_user_db(&user_db(dir.path())).unwrap();
+        sqlite_lend(&user_conn, "qt:1", "Paul", None).unwrap();
+        sqlite_lend(&user_conn, "qt:2", "Marie", Some(days(7))).unwrap();
+        sqlite_lend(&user_conn, "hdd:ELORA_1/Gone.mkv", "Léa", Some(days(-3))).unwrap();
+        sqlite_lend(&user_conn, "qt:3", "Luc", Some(days(1))).unwrap();
+        sqlite_return(&user_conn, "qt:3").unwrap();
+
+        let loans = sqlite_current_loans(&conn).unwrap();
+        // The ones expected back first first, the ones without a due date last
+        let borrowers: Vec<_> = loans.iter().map(|loan| loan.loan.borrower.as_str()).collect();
+        assert_eq!(borrowers, ["Léa", "Marie", "Paul"]);
+        // Not in the catalog anymore
+        assert!(loans[0].support.is_none());
+        assert_eq!(loans[0].title, "hdd:ELORA_1/Gone.mkv");
+        let mepris = &loans[1];
+        assert_eq!((mepris.title.as_str(), mepris.film_code), ("Le Mépris", Some(4)));
+        let support = mepris.support.as_ref().unwrap();
+        assert_eq!(
+            (support.code, support.location.as_str(), support.on_loan),
+            (2, "ARMOIRE_1", true)
+        );
+        assert_eq!(loans[2].film_code, None);
+    }
+
+    #[test]
+    fn overdue() {
+        let loan = |due_on: Option<NaiveDate>, returned_on: Option<NaiveDate>| Loan {
+            borrower: "Paul".to_string(),
+            lent_on: days(-30),
+            due_on,
+            returned_on,
+        };
+        assert!(loan(Some(days(-1)), None).is_overdue());
+        assert!(!loan(Some(today()), None).is_overdue());
+        assert!(!loan(Some(days(1)), None).is_overdue());
+        assert!(!loan(None, None).is_overdue());
+        assert!(!loan(Some(days(-1)), Some(today())).is_overdue());
+    }
+
+    #[test]
+    fn local_loans_override_the_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = merged_db(dir.path(), TAPES, &[]);
+        let user_conn = open_user_db(&user_db(dir.path())).unwrap();
+        // Until we record a loan, Tape.ON_LOAN
+        assert!(on_loan(&conn, 1));
+        assert!(!on_loan(&conn, 2));
+
+        // Lent according to the catalog, but we got it back
+        sqlite_lend(&user_conn, "qt:1", "Paul", None).unwrap();
+        assert!(on_loan(&conn, 1));
+        sqlite_return(&user_conn, "qt:1").unwrap();
+        assert!(!on_loan(&conn, 1));
+        // Not lent according to the catalog, but we lent it
+        sqlite_lend(&user_conn, "qt:2", "Marie", None).unwrap();
+        assert!(on_loan(&conn, 2));
+    }
+
+    /// The same file, on two HDDs
+    fn copies() -> [TapeRow; 2] {
+        ["ELORA_3", "ELORA_9"].map(|location| TapeRow {
+            size: Some(1_000_000),
+            fingerprint: Some("0123456789abcdef".to_string()),
+            ..hdd_file(location, "Films/Playtime.mkv", 120)
+        })
+    }
+
+    /// The support key of the file at `location`
+    fn key(conn: &Connection, location: &str) -> String {
+        let code = conn
+            .query_row("SELECT CODE_TAPE FROM Tape WHERE LOCATION=?1", [location], |row| row.get(0))
+            .unwrap();
+        sqlite_support(conn, code).unwrap().key
+    }
+
+    #[test]
+    fn copies_on_several_hdds_are_different_supports() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = merged_db(dir.path(), "", &copies());
+        let user_conn = open_user_db(&user_db(dir.path())).unwrap();
+        let (key_3, key_9) = (key(&conn, "ELORA_3"), key(&conn, "ELORA_9"));
+        assert_eq!(key_3, "fp:ELORA_3/0123456789abcdef");
+        assert_ne!(key_3, key_9);
+
+        let data = UserData { favourite: true, notes: "VF".to_string(), ..Default::default() };
+        sqlite_set_user_data(&user_conn, 0, &key_3, &data).unwrap();
+        assert_eq!(sqlite_user_data(&user_conn, 0, &key_3).unwrap(), data);
+        assert_eq!(sqlite_user_data(&user_conn, 0, &key_9).unwrap(), UserData::default());
+
+        // Lending one copy doesn't lend the other
+        sqlite_lend(&user_conn, &key_9, "Paul", None).unwrap();
+        let loans = sqlite_current_loans(&conn).unwrap();
+        assert_eq!(loans.len(), 1);
+        assert_eq!(loans[0].support.as_ref().map(|s| s.location.as_str()), Some("ELORA_9"));
+        let on_loan: Vec<(String, bool)> = conn
+            .prepare(&format!("SELECT LOCATION, {} FROM Tape ORDER BY LOCATION", *ON_LOAN_SQL))
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(on_loan, [("ELORA_3".to_string(), false), ("ELORA_9".to_string(), true)]);
+    }
+}